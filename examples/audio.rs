@@ -4,7 +4,7 @@ use bevy_kira_components::prelude::*;
 
 fn main() {
     App::new()
-        .add_plugins((DefaultPlugins, AudioPlugin))
+        .add_plugins((DefaultPlugins, AudioPlugin::default()))
         .add_systems(Startup, setup)
         .run();
 }
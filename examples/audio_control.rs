@@ -10,7 +10,7 @@ use bevy_kira_components::prelude::*;
 fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
-        .add_plugins(AudioPlugin)
+        .add_plugins(AudioPlugin::default())
         .add_systems(Startup, setup)
         .add_systems(Update, (update_speed, pause, volume))
         .run();
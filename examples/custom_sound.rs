@@ -87,11 +87,30 @@
 //!
 //! ## Settings
 //!
-//! TODO: Writing this triggered some existential crisis over the API 😵‍💫
+//! Users don't create the [`SoundData`] themselves: the sound is created from the ECS, out of an
+//! asset (implementing [`AudioSource`]) and a settings component, the `Settings` associated type
+//! of [`AudioSource`]. The asset holds what is shared between all the instances of the sound
+//! (like audio data, or here, nothing at all, see [`SineWave`]), while the settings component
+//! holds what is specific to each instance, here the initial frequency ([`SineWaveSettings`]).
+//! The settings component must implement [`Default`], for the sound to be spawned with default
+//! settings.
+//!
+//! [`AudioSource::create_handle`] is where both come together: it builds the [`SoundData`] from
+//! the asset and the settings, and plays it with the [`SoundPlayer`] it receives, which inserts
+//! the effects requested by other components (e.g. meters) and tracks the voice of the sound. The
+//! output destination it is given must be passed along to the sound, as it routes the sound to
+//! its track, or to its spatial emitter.
 //!
 //! ## Usage in the ECS
 //!
-//! TODO: Writing this triggered some existential crisis over the API 😵‍💫
+//! Each audio source type needs its [`AudioSourcePlugin`] to be added to the app. The plugin
+//! creates the sound when an entity with an [`AudioBundle`](bevy_kira_components::sources::AudioBundle)
+//! of that source is spawned, once the asset is available, and inserts the handle returned by
+//! [`AudioSource::create_handle`] into an [`AudioHandle`] component. Systems can then query for
+//! `&mut AudioHandle<SineWaveHandle>` to control the sound, as done in `change_frequency` below.
+//!
+//! Note that simple generators like this one are already provided by the crate with the
+//! [`Synth`] audio source, which supports several waveforms and envelopes.
 
 use std::convert::Infallible;
 use std::f32::consts::TAU;
@@ -112,12 +131,13 @@ fn main() {
     App::new()
         .add_plugins((
             DefaultPlugins,
-            AudioPlugin,
+            AudioPlugin::default(),
             // The audio source plugin is generic over audio sources; use it to register systems
             // that will manage your custom audio source for you.
             AudioSourcePlugin::<SineWave>::default(),
         ))
         .add_systems(Startup, setup)
+        .add_systems(Update, change_frequency)
         .run();
 }
 
@@ -134,6 +154,25 @@ fn setup(mut commands: Commands, mut assets: ResMut<Assets<SineWave>>) {
     });
 }
 
+/// Moves the frequency of the sine waves up or down a semitone with the arrow keys, through their
+/// handles.
+fn change_frequency(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut q_sine_waves: Query<(&mut SineWaveSettings, &mut AudioHandle<SineWaveHandle>)>,
+) {
+    let semitones = if keyboard.just_pressed(KeyCode::ArrowUp) {
+        1.0
+    } else if keyboard.just_pressed(KeyCode::ArrowDown) {
+        -1.0
+    } else {
+        return;
+    };
+    for (mut settings, mut handle) in &mut q_sine_waves {
+        settings.frequency *= f32::powf(2.0, semitones / 12.0);
+        handle.set_frequency(settings.frequency, Tween::default());
+    }
+}
+
 /// Enum for commands the Handle (controlled within Bevy systems) can send to the sound (in the
 /// audio thread).
 ///
@@ -285,7 +324,7 @@ impl AudioSource for SineWave {
 
     fn create_handle(
         &self,
-        manager: &mut kira::manager::AudioManager<AudioBackend>,
+        manager: &mut SoundPlayer,
         settings: &Self::Settings,
         output_destination: kira::OutputDestination,
     ) -> Result<Self::Handle, Self::Error> {
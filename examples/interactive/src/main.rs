@@ -14,7 +14,12 @@ mod ui;
 
 fn main() {
     App::new()
-        .add_plugins((DefaultPlugins, AudioPlugin, DiagnosticsUiPlugin, UiPlugin))
+        .add_plugins((
+            DefaultPlugins,
+            AudioPlugin::default(),
+            DiagnosticsUiPlugin,
+            UiPlugin,
+        ))
         .add_systems(Startup, init)
         .add_systems(Update, handle_interactive_sound)
         .run();
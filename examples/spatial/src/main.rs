@@ -2,32 +2,28 @@ use bevy::color::palettes::css::{GRAY, GREEN};
 use bevy::math::vec3;
 use bevy::prelude::*;
 
-use bevy_kira_components::kira::sound::{PlaybackRate, Region};
-use bevy_kira_components::kira::tween::Tween;
+use bevy_kira_components::kira::sound::Region;
 use bevy_kira_components::prelude::*;
 use bevy_kira_components::AudioPlugin;
 use diagnostics_ui::DiagnosticsUiPlugin;
 
 use crate::camera::{CameraPlugin, FpsCam};
-use crate::motion::{Motion, MotionPlugin};
 use crate::ui::UiPlugin;
 
 mod camera;
-mod motion;
 mod ui;
 
 fn main() {
     App::new()
         .add_plugins((
             DefaultPlugins,
-            AudioPlugin,
+            AudioPlugin::default(),
             DiagnosticsUiPlugin,
-            MotionPlugin,
             CameraPlugin,
             UiPlugin,
         ))
         .add_systems(Startup, (init_camera, init_objects))
-        .add_systems(Update, (rotate_objects, fake_doppler_effect))
+        .add_systems(Update, rotate_objects)
         .run();
 }
 
@@ -65,8 +61,13 @@ fn init_objects(
         ))
         .with_children(|children| {
             children.spawn((
-                Doppler(1.0),
-                SpatialEmitter::default(),
+                SpatialEmitter {
+                    doppler: Some(Doppler {
+                        speed_of_sound: SPEED_OF_SOUND,
+                        ..default()
+                    }),
+                    ..default()
+                },
                 AudioFileBundle {
                     source,
                     settings: AudioFileSettings {
@@ -121,38 +122,9 @@ fn rotate_objects(time: Res<Time>, mut q: Query<(&mut Transform, &Rotate)>) {
     }
 }
 
-/// Stores the computed doppler value to display in UI
-#[derive(Component)]
-struct Doppler(f32);
-
 /// Marker component for the UI
 #[derive(Component)]
 struct DopplerUI;
 
+/// Speed of sound in the scene, way slower than in the real world to exaggerate the Doppler effect
 const SPEED_OF_SOUND: f32 = 20.0;
-
-// Fake the doppler pitch shift effect by playing the loop faster or slower
-fn fake_doppler_effect(
-    mut q: Query<
-        (
-            &mut AudioHandle<AudioFileHandle>,
-            &mut Doppler,
-            &GlobalTransform,
-            &Motion,
-        ),
-        With<SpatialEmitter>,
-    >,
-    q_cameras: Query<(&GlobalTransform, &Motion), With<FpsCam>>,
-) {
-    let Ok((cam_transform, cam_motion)) = q_cameras.get_single() else {
-        // Motion has not been added yet, wait one tick
-        return;
-    };
-    let cam_transform = cam_transform.compute_transform();
-    for (mut handle, mut doppler, transform, motion) in &mut q {
-        let local_dir = Vec3::normalize(cam_transform.translation - transform.translation());
-        doppler.0 = (SPEED_OF_SOUND - cam_motion.motion().dot(local_dir))
-            / (SPEED_OF_SOUND - motion.motion().dot(local_dir));
-        handle.set_playback_rate(PlaybackRate::Factor(doppler.0 as _), Tween::default());
-    }
-}
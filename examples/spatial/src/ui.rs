@@ -1,5 +1,6 @@
-use crate::{Doppler, DopplerUI};
+use crate::DopplerUI;
 use bevy::prelude::*;
+use bevy_kira_components::prelude::*;
 
 pub struct UiPlugin;
 
@@ -50,8 +51,14 @@ fn ui_init(mut commands: Commands) {
         });
 }
 
-fn update_ui_doppler(mut q_text: Query<&mut Text, With<DopplerUI>>, q_doppler: Query<&Doppler>) {
+fn update_ui_doppler(
+    mut q_text: Query<&mut Text, With<DopplerUI>>,
+    q_doppler: Query<&DopplerShift>,
+) {
+    let Ok(doppler) = q_doppler.get_single().map(DopplerShift::factor) else {
+        // The emitter has not been set up yet
+        return;
+    };
     let mut text = q_text.single_mut();
-    let doppler = q_doppler.single().0;
     text.sections[1].value = format!("{doppler:1.2}x");
 }
@@ -0,0 +1,298 @@
+//! Level metering of audio sources and tracks.
+use std::f64::consts::PI;
+
+use bevy::prelude::*;
+use kira::clock::clock_info::ClockInfoProvider;
+use kira::effect::Effect;
+use kira::modulator::value_provider::ModulatorValueProvider;
+use kira::Frame;
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+
+use crate::effects::PendingEffects;
+
+/// Number of readings that can be in flight between the audio thread and Bevy. Readings are sent
+/// once per audio callback, so this leaves room for a lot of callbacks per frame.
+const READINGS_CAPACITY: usize = 64;
+
+/// Duration of the blocks over which loudness is measured, as per ITU-R BS.1770.
+const LOUDNESS_BLOCK_SECONDS: f64 = 0.1;
+
+/// Number of loudness blocks in the short-term loudness window (3 seconds).
+const SHORT_TERM_BLOCKS: usize = 30;
+
+/// Component measuring the level of the audio going through an audio source or a track.
+///
+/// Insert it along with the [`AudioBundle`](crate::prelude::AudioBundle) or
+/// [`AudioTrackBundle`](crate::prelude::AudioTrackBundle), as the metering effect needs to be
+/// inserted in the signal path when the source or track is created. Inserting it afterwards has no
+/// effect.
+///
+/// The measured values are updated every frame from the audio processed since the last frame.
+#[derive(Component, Default)]
+pub struct AudioMeter {
+    peak: f32,
+    rms: f32,
+    loudness: f32,
+    readings: Option<HeapConsumer<MeterReading>>,
+}
+
+impl AudioMeter {
+    /// Highest absolute sample value (across channels) since the last frame.
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+
+    /// Highest absolute sample value (across channels) since the last frame, in decibels.
+    pub fn peak_db(&self) -> f32 {
+        amplitude_to_db(self.peak)
+    }
+
+    /// Root mean square value (averaged across channels) of the audio since the last frame.
+    pub fn rms(&self) -> f32 {
+        self.rms
+    }
+
+    /// Root mean square value (averaged across channels) of the audio since the last frame, in
+    /// decibels.
+    pub fn rms_db(&self) -> f32 {
+        amplitude_to_db(self.rms)
+    }
+
+    /// Short-term loudness (over the last 3 seconds, or the audio measured so far during the first
+    /// 3 seconds) in LUFS, as specified in ITU-R BS.1770. This is negative infinity when the audio
+    /// is silent.
+    pub fn loudness_short_term(&self) -> f32 {
+        self.loudness
+    }
+}
+
+fn amplitude_to_db(amplitude: f32) -> f32 {
+    20.0 * amplitude.log10()
+}
+
+/// Measurement sent from the audio thread at the end of an audio callback.
+#[derive(Debug, Copy, Clone)]
+struct MeterReading {
+    peak: f32,
+    sum_squares: f64,
+    num_samples: u32,
+    loudness: f32,
+}
+
+impl MeterReading {
+    const SILENCE: Self = Self {
+        peak: 0.0,
+        sum_squares: 0.0,
+        num_samples: 0,
+        loudness: f32::NEG_INFINITY,
+    };
+}
+
+pub(super) fn add_meters(
+    mut commands: Commands,
+    mut q: Query<(Entity, &mut AudioMeter), Added<AudioMeter>>,
+) {
+    for (entity, mut meter) in &mut q {
+        let (producer, consumer) = HeapRb::new(READINGS_CAPACITY).split();
+        meter.readings = Some(consumer);
+        PendingEffects::push(&mut commands, entity, Box::new(MeterEffect::new(producer)));
+    }
+}
+
+pub(super) fn update_meters(mut q: Query<&mut AudioMeter>) {
+    for mut meter in &mut q {
+        let Some(readings) = meter.readings.as_mut() else {
+            continue;
+        };
+        let mut last = None;
+        let mut peak = 0f32;
+        let mut sum_squares = 0.0;
+        let mut num_samples = 0;
+        while let Some(reading) = readings.pop() {
+            peak = peak.max(reading.peak);
+            sum_squares += reading.sum_squares;
+            num_samples += reading.num_samples;
+            last = Some(reading);
+        }
+        // No audio was processed since last frame, keep the previous values
+        let Some(last) = last else {
+            continue;
+        };
+        meter.peak = peak;
+        meter.rms = if num_samples > 0 {
+            (sum_squares / num_samples as f64).sqrt() as f32
+        } else {
+            0.0
+        };
+        meter.loudness = last.loudness;
+    }
+}
+
+/// Biquad filter, used to implement the K-weighting filter of the loudness measurement.
+#[derive(Debug, Default, Copy, Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, input: f64) -> f64 {
+        // Transposed direct form II
+        let output = self.b[0] * input + self.state[0];
+        self.state[0] = self.b[1] * input - self.a[0] * output + self.state[1];
+        self.state[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+
+    /// High shelf of the first stage of the K-weighting filter, modeling the acoustic effects of
+    /// the head.
+    fn k_weighting_shelf(sample_rate: f64) -> Self {
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (PI * f0 / sample_rate).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        Self {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            state: [0.0; 2],
+        }
+    }
+
+    /// High pass of the second stage of the K-weighting filter (RLB weighting curve).
+    fn k_weighting_high_pass(sample_rate: f64) -> Self {
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        Self {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            state: [0.0; 2],
+        }
+    }
+}
+
+/// Effect measuring the audio passing through it, without modifying it.
+struct MeterEffect {
+    readings: HeapProducer<MeterReading>,
+    peak: f32,
+    sum_squares: f64,
+    num_samples: u32,
+    /// K-weighting filters, for the left and right channels
+    k_weighting: [[Biquad; 2]; 2],
+    block_length: u32,
+    block_position: u32,
+    block_sum_squares: f64,
+    /// Mean square of the last loudness blocks, used as a ring buffer
+    blocks: [f64; SHORT_TERM_BLOCKS],
+    block_index: usize,
+    /// Number of blocks measured so far, up to the size of the ring buffer
+    filled_blocks: usize,
+    loudness: f32,
+}
+
+impl MeterEffect {
+    fn new(readings: HeapProducer<MeterReading>) -> Self {
+        Self {
+            readings,
+            peak: 0.0,
+            sum_squares: 0.0,
+            num_samples: 0,
+            k_weighting: Default::default(),
+            block_length: 0,
+            block_position: 0,
+            block_sum_squares: 0.0,
+            blocks: [0.0; SHORT_TERM_BLOCKS],
+            block_index: 0,
+            filled_blocks: 0,
+            loudness: f32::NEG_INFINITY,
+        }
+    }
+
+    fn measure_loudness(&mut self, frame: Frame) {
+        let mut sum = 0.0;
+        for (filters, sample) in self.k_weighting.iter_mut().zip([frame.left, frame.right]) {
+            let filtered = filters
+                .iter_mut()
+                .fold(sample as f64, |sample, filter| filter.process(sample));
+            sum += filtered * filtered;
+        }
+        self.block_sum_squares += sum;
+        self.block_position += 1;
+        if self.block_position < self.block_length {
+            return;
+        }
+
+        self.blocks[self.block_index] = self.block_sum_squares / self.block_length as f64;
+        self.block_index = (self.block_index + 1) % SHORT_TERM_BLOCKS;
+        self.filled_blocks = (self.filled_blocks + 1).min(SHORT_TERM_BLOCKS);
+        self.block_position = 0;
+        self.block_sum_squares = 0.0;
+        // Blocks not measured yet would be counted as silence
+        let mean_square = self.blocks.iter().sum::<f64>() / self.filled_blocks as f64;
+        self.loudness = (-0.691 + 10.0 * mean_square.log10()) as f32;
+    }
+}
+
+impl Effect for MeterEffect {
+    fn init(&mut self, sample_rate: u32) {
+        let sample_rate = sample_rate as f64;
+        let filters = [
+            Biquad::k_weighting_shelf(sample_rate),
+            Biquad::k_weighting_high_pass(sample_rate),
+        ];
+        self.k_weighting = [filters; 2];
+        self.block_length = (LOUDNESS_BLOCK_SECONDS * sample_rate) as u32;
+        self.block_position = 0;
+        self.block_sum_squares = 0.0;
+    }
+
+    fn on_change_sample_rate(&mut self, sample_rate: u32) {
+        self.init(sample_rate);
+    }
+
+    fn on_start_processing(&mut self) {
+        if self.num_samples == 0 {
+            return;
+        }
+        // Readings are dropped if Bevy does not consume them fast enough
+        let _ = self.readings.push(MeterReading {
+            peak: self.peak,
+            sum_squares: self.sum_squares,
+            num_samples: self.num_samples,
+            loudness: self.loudness,
+        });
+        self.peak = 0.0;
+        self.sum_squares = 0.0;
+        self.num_samples = 0;
+    }
+
+    fn process(
+        &mut self,
+        input: Frame,
+        _dt: f64,
+        _clock_info_provider: &ClockInfoProvider,
+        _modulator_value_provider: &ModulatorValueProvider,
+    ) -> Frame {
+        self.peak = self.peak.max(input.left.abs()).max(input.right.abs());
+        self.sum_squares += (input.left as f64).powi(2) + (input.right as f64).powi(2);
+        self.num_samples += 2;
+        self.measure_loudness(input);
+        input
+    }
+}
+
+impl Drop for MeterEffect {
+    fn drop(&mut self) {
+        // Signal the end of the audio to the meter so that it doesn't stay stuck on the last values
+        let _ = self.readings.push(MeterReading::SILENCE);
+    }
+}
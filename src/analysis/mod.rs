@@ -0,0 +1,68 @@
+//! Analysis of the audio playing through sources and tracks, for use in UIs and gameplay.
+use bevy::prelude::*;
+use thiserror::Error;
+
+use crate::{AudioEffectsSetup, AudioPlaybackSet};
+
+pub mod meter;
+pub mod onset;
+pub mod spectrum;
+mod tap;
+
+#[doc(hidden)]
+#[allow(missing_docs)]
+pub mod prelude {
+    pub use super::meter::AudioMeter;
+    pub use super::onset::{AudioOnset, OnsetDetector, OnsetSettings};
+    pub use super::spectrum::{AudioSpectrum, SpectrumBands, SpectrumSettings, WindowFunction};
+    pub use super::AnalysisSettingsError;
+}
+
+/// Enumeration of the invalid settings of the audio analyses.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum AnalysisSettingsError {
+    /// The number of samples of the FFT is not a power of two.
+    #[error("FFT size must be a power of two, got {0}")]
+    FftSizeNotPowerOfTwo(usize),
+    /// The hop size is zero or larger than the FFT size.
+    #[error("Hop size must be between 1 and the FFT size, got {0}")]
+    InvalidHopSize(usize),
+    /// The frequency bands are empty, or their frequency range is.
+    #[error("Spectrum bands must be at least 1 over a range of positive frequencies, got {0:?}")]
+    InvalidBands(spectrum::SpectrumBands),
+    /// The range of tempos is empty or starts below the lowest tempo that can be estimated.
+    #[error(
+        "Tempo range must go from at least {min} BPM to a higher tempo, got {0:?}",
+        min = onset::MIN_TEMPO
+    )]
+    InvalidTempoRange((f32, f32)),
+}
+
+/// Audio analysis plugin. This is an internal plugin, useful for some separation of concerns.
+///
+/// It is automatically added by the main [`AudioPlugin`](crate::AudioPlugin).
+pub(crate) struct AudioAnalysisPlugin;
+
+impl Plugin for AudioAnalysisPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<onset::AudioOnset>()
+            .add_systems(
+                PostUpdate,
+                (
+                    meter::add_meters,
+                    spectrum::add_spectrums,
+                    onset::add_onset_detectors,
+                )
+                    .in_set(AudioEffectsSetup),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    meter::update_meters,
+                    spectrum::update_spectrums,
+                    onset::update_onset_detectors,
+                )
+                    .in_set(AudioPlaybackSet::Cleanup),
+            );
+    }
+}
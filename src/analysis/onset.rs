@@ -0,0 +1,301 @@
+//! Onset detection and tempo estimation of audio sources and tracks.
+//!
+//! Onsets are detected using the spectral flux of the signal, i.e. how much the energy increases
+//! between successive spectra, compared against an adaptive threshold. The tempo is estimated from
+//! the periodicity of the spectral flux.
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+use crate::analysis::spectrum::WindowFunction;
+use crate::analysis::tap::{tap, TapReceiver};
+use crate::analysis::AnalysisSettingsError;
+use crate::effects::PendingEffects;
+
+/// Number of spectral flux values around the current one used to compute the adaptive threshold.
+const THRESHOLD_WINDOW: usize = 16;
+
+/// Minimum detection threshold, preventing the slightest noise after silence from being detected
+/// as an onset.
+const MIN_THRESHOLD: f32 = 0.1;
+
+/// Tempo (in beats per minute) favored when estimating the tempo, to reduce the chance of picking
+/// half or double of the perceived tempo.
+const PREFERRED_TEMPO: f32 = 120.0;
+
+/// Duration of the spectral flux history used to estimate the tempo, in seconds.
+const TEMPO_HISTORY_SECONDS: f32 = 8.0;
+
+/// Lowest tempo that can be estimated, in beats per minute, the history needing to hold two
+/// beats for their periodicity to show: 15 BPM.
+pub(super) const MIN_TEMPO: f32 = 2.0 * 60.0 / TEMPO_HISTORY_SECONDS;
+
+/// Event sent when an onset (the start of a note, a drum hit, ...) is detected in the audio of
+/// an entity with an [`OnsetDetector`].
+#[derive(Debug, Copy, Clone, Event)]
+pub struct AudioOnset {
+    /// Entity whose audio contains the onset.
+    pub entity: Entity,
+    /// Time of the onset, in seconds of audio since the detector started receiving audio.
+    pub time: f64,
+    /// Strength of the onset, as the ratio of the spectral flux over the detection threshold.
+    /// Always greater than 1; stronger onsets have higher values.
+    pub strength: f32,
+}
+
+/// Settings of the onset detection.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OnsetSettings {
+    /// Number of samples each spectrum is computed over. Must be a power of two.
+    pub fft_size: usize,
+    /// Number of samples between successive spectra. Smaller values give a better time
+    /// resolution, at the cost of more computations.
+    pub hop_size: usize,
+    /// Multiplier applied to the local average of the spectral flux to get the detection
+    /// threshold. Lower values detect more onsets.
+    pub threshold: f32,
+    /// Minimum time between two onsets, in seconds.
+    pub min_interval: f32,
+    /// Range of tempos (in beats per minute) considered when estimating the tempo. Tempos are
+    /// estimated over the last 8 seconds of audio, which must hold two beats: the range cannot
+    /// start below 15 BPM.
+    pub tempo_range: (f32, f32),
+}
+
+impl Default for OnsetSettings {
+    fn default() -> Self {
+        Self {
+            fft_size: 1024,
+            hop_size: 512,
+            threshold: 1.5,
+            min_interval: 0.05,
+            tempo_range: (60.0, 200.0),
+        }
+    }
+}
+
+/// Component detecting onsets in the audio going through an audio source or a track, and
+/// estimating its tempo. Detected onsets are sent as [`AudioOnset`] events.
+///
+/// Insert it along with the [`AudioBundle`](crate::prelude::AudioBundle) or
+/// [`AudioTrackBundle`](crate::prelude::AudioTrackBundle), as the detector needs to tap into the
+/// signal path when the source or track is created. Inserting it afterwards has no effect.
+#[derive(Component)]
+pub struct OnsetDetector {
+    settings: OnsetSettings,
+    tempo: Option<f32>,
+    detector: Option<Detector>,
+}
+
+impl Default for OnsetDetector {
+    fn default() -> Self {
+        Self::new(OnsetSettings::default()).expect("Default onset settings are valid")
+    }
+}
+
+impl OnsetDetector {
+    /// Creates a new onset detector with the given settings.
+    ///
+    /// Returns an error if the FFT size is not a power of two, if the hop size is zero or larger
+    /// than the FFT size, or if the tempo range is invalid (see [`OnsetSettings::tempo_range`]).
+    pub fn new(settings: OnsetSettings) -> Result<Self, AnalysisSettingsError> {
+        if !settings.fft_size.is_power_of_two() {
+            return Err(AnalysisSettingsError::FftSizeNotPowerOfTwo(
+                settings.fft_size,
+            ));
+        }
+        if !(1..=settings.fft_size).contains(&settings.hop_size) {
+            return Err(AnalysisSettingsError::InvalidHopSize(settings.hop_size));
+        }
+        let (min_tempo, max_tempo) = settings.tempo_range;
+        // Also rejects NaNs
+        if !(min_tempo >= MIN_TEMPO && max_tempo > min_tempo && max_tempo.is_finite()) {
+            return Err(AnalysisSettingsError::InvalidTempoRange(
+                settings.tempo_range,
+            ));
+        }
+        Ok(Self {
+            settings,
+            tempo: None,
+            detector: None,
+        })
+    }
+
+    /// Settings of this onset detector.
+    pub fn settings(&self) -> &OnsetSettings {
+        &self.settings
+    }
+
+    /// Estimated tempo of the audio, in beats per minute. This is `None` until enough audio has
+    /// been processed to estimate it.
+    pub fn tempo(&self) -> Option<f32> {
+        self.tempo
+    }
+}
+
+/// Internal state of the detector, created when the signal gets tapped.
+struct Detector {
+    receiver: TapReceiver,
+    samples: Vec<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    previous_spectrum: Vec<f32>,
+    /// Recent spectral flux values, used for the adaptive threshold and the tempo estimation
+    flux: VecDeque<f32>,
+    /// Number of samples processed so far (i.e. position of the next hop)
+    position: u64,
+    last_onset: Option<f64>,
+    hops_since_tempo: usize,
+}
+
+impl Detector {
+    fn new(receiver: TapReceiver, settings: &OnsetSettings) -> Self {
+        Self {
+            receiver,
+            samples: Vec::with_capacity(settings.fft_size * 2),
+            fft: FftPlanner::new().plan_fft_forward(settings.fft_size),
+            window: WindowFunction::Hann.coefficients(settings.fft_size),
+            buffer: vec![Complex::default(); settings.fft_size],
+            previous_spectrum: vec![0.0; settings.fft_size / 2 + 1],
+            flux: VecDeque::new(),
+            position: 0,
+            last_onset: None,
+            hops_since_tempo: 0,
+        }
+    }
+
+    /// Computes the spectral flux of the next window of samples, that is, the sum of the
+    /// increases in log-magnitude of each bin compared to the previous window.
+    fn spectral_flux(&mut self, fft_size: usize) -> f32 {
+        for ((bin, sample), window) in self
+            .buffer
+            .iter_mut()
+            .zip(&self.samples[..fft_size])
+            .zip(&self.window)
+        {
+            *bin = Complex::new(sample * window, 0.0);
+        }
+        self.fft.process(&mut self.buffer);
+        let scale = 2.0 / self.window.iter().sum::<f32>();
+        let mut flux = 0.0;
+        for (bin, previous) in self.buffer.iter().zip(&mut self.previous_spectrum) {
+            // Log compression makes the flux less dependent on the overall level
+            let magnitude = (1.0 + 100.0 * scale * bin.norm()).ln();
+            flux += (magnitude - *previous).max(0.0);
+            *previous = magnitude;
+        }
+        flux
+    }
+
+    /// Estimates the tempo from the autocorrelation of the spectral flux, returning the tempo with
+    /// the strongest periodicity in the given range. Periodicities are weighted by how close they
+    /// are to [`PREFERRED_TEMPO`] (on a logarithmic scale), as tempos at half or double the
+    /// perceived tempo also show strong periodicities.
+    fn estimate_tempo(&self, hop_rate: f32, tempo_range: (f32, f32)) -> Option<f32> {
+        let (min_tempo, max_tempo) = tempo_range;
+        let min_lag = (60.0 * hop_rate / max_tempo).floor().max(1.0) as usize;
+        let max_lag = tempo_lag(hop_rate, min_tempo);
+        if self.flux.len() < 2 * max_lag {
+            return None;
+        }
+        let mean = self.flux.iter().sum::<f32>() / self.flux.len() as f32;
+        let centered = self.flux.iter().map(|f| f - mean).collect::<Vec<_>>();
+        let (best_lag, best_correlation) = (min_lag..=max_lag)
+            .map(|lag| {
+                let correlation = centered
+                    .iter()
+                    .zip(&centered[lag..])
+                    .map(|(a, b)| a * b)
+                    .sum::<f32>()
+                    / (centered.len() - lag) as f32;
+                let tempo = 60.0 * hop_rate / lag as f32;
+                let octaves = (tempo / PREFERRED_TEMPO).log2();
+                (lag, correlation * f32::exp(-0.5 * octaves * octaves))
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        (best_correlation > 0.0).then(|| 60.0 * hop_rate / best_lag as f32)
+    }
+}
+
+/// Number of hops between two beats at the given tempo, rounded up.
+fn tempo_lag(hop_rate: f32, tempo: f32) -> usize {
+    (60.0 * hop_rate / tempo).ceil() as usize
+}
+
+pub(super) fn add_onset_detectors(
+    mut commands: Commands,
+    mut q: Query<(Entity, &mut OnsetDetector), Added<OnsetDetector>>,
+) {
+    for (entity, mut onset_detector) in &mut q {
+        let (effect, receiver) = tap(onset_detector.settings.fft_size.max(8192) * 4);
+        onset_detector.detector = Some(Detector::new(receiver, &onset_detector.settings));
+        PendingEffects::push(&mut commands, entity, Box::new(effect));
+    }
+}
+
+pub(super) fn update_onset_detectors(
+    mut writer: EventWriter<AudioOnset>,
+    mut q: Query<(Entity, &mut OnsetDetector)>,
+) {
+    for (entity, mut onset_detector) in &mut q {
+        let onset_detector = &mut *onset_detector;
+        let settings = onset_detector.settings;
+        let Some(detector) = onset_detector.detector.as_mut() else {
+            continue;
+        };
+        detector.receiver.receive_into(&mut detector.samples);
+        let sample_rate = detector.receiver.sample_rate();
+        if sample_rate == 0 {
+            continue;
+        }
+        let hop_rate = sample_rate as f32 / settings.hop_size as f32;
+        // Round up to fit two periods of the lowest tempo
+        let max_history = ((TEMPO_HISTORY_SECONDS * hop_rate) as usize)
+            .max(2 * tempo_lag(hop_rate, settings.tempo_range.0));
+
+        while detector.samples.len() >= settings.fft_size {
+            let flux = detector.spectral_flux(settings.fft_size);
+            detector.samples.drain(..settings.hop_size);
+            detector.position += settings.hop_size as u64;
+            detector.hops_since_tempo += 1;
+
+            let recent = detector.flux.iter().rev().take(THRESHOLD_WINDOW);
+            let count = recent.len();
+            let average = recent.sum::<f32>() / count.max(1) as f32;
+            detector.flux.push_back(flux);
+            if detector.flux.len() > max_history {
+                detector.flux.pop_front();
+            }
+            // Wait for the threshold to stabilize before detecting onsets
+            if count < THRESHOLD_WINDOW {
+                continue;
+            }
+
+            let threshold = (settings.threshold * average).max(MIN_THRESHOLD);
+            let time = detector.position as f64 / sample_rate as f64;
+            let can_trigger = detector
+                .last_onset
+                .map_or(true, |last| time - last >= settings.min_interval as f64);
+            if flux > threshold && can_trigger {
+                detector.last_onset = Some(time);
+                writer.send(AudioOnset {
+                    entity,
+                    time,
+                    strength: flux / threshold,
+                });
+            }
+        }
+
+        // Estimating the tempo is comparatively expensive, only do it about once per second
+        if detector.hops_since_tempo as f32 >= hop_rate {
+            detector.hops_since_tempo = 0;
+            if let Some(tempo) = detector.estimate_tempo(hop_rate, settings.tempo_range) {
+                onset_detector.tempo = Some(tempo);
+            }
+        }
+    }
+}
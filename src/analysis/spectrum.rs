@@ -0,0 +1,285 @@
+//! Spectrum analysis of audio sources and tracks.
+use std::f32::consts::TAU;
+use std::sync::{Arc, OnceLock};
+
+use bevy::prelude::*;
+use bevy::tasks::AsyncComputeTaskPool;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+use crate::analysis::tap::{tap, TapReceiver};
+use crate::analysis::AnalysisSettingsError;
+use crate::effects::PendingEffects;
+
+/// Window function applied to the signal before computing its spectrum.
+///
+/// Windowing reduces spectral leakage, at the cost of frequency resolution.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum WindowFunction {
+    /// No windowing. Best frequency resolution, but the most leakage.
+    Rectangular,
+    /// Hann window, a good general-purpose default.
+    #[default]
+    Hann,
+    /// Hamming window.
+    Hamming,
+    /// Blackman window. Least leakage, but the worst frequency resolution.
+    Blackman,
+}
+
+impl WindowFunction {
+    pub(crate) fn coefficients(&self, size: usize) -> Vec<f32> {
+        let n = (size - 1).max(1) as f32;
+        (0..size)
+            .map(|i| {
+                let x = i as f32 / n;
+                match self {
+                    Self::Rectangular => 1.0,
+                    Self::Hann => 0.5 - 0.5 * f32::cos(TAU * x),
+                    Self::Hamming => 0.54 - 0.46 * f32::cos(TAU * x),
+                    Self::Blackman => {
+                        0.42 - 0.5 * f32::cos(TAU * x) + 0.08 * f32::cos(2.0 * TAU * x)
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Logarithmically spaced frequency bands, grouping the bins of the spectrum in a way that's
+/// closer to how pitch is perceived.
+///
+/// There must be at least one band, and the frequencies must be positive, the lowest one below
+/// the highest one.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpectrumBands {
+    /// Number of bands.
+    pub count: usize,
+    /// Lower frequency of the first band, in Hz.
+    pub min_frequency: f32,
+    /// Upper frequency of the last band, in Hz.
+    pub max_frequency: f32,
+}
+
+impl Default for SpectrumBands {
+    fn default() -> Self {
+        Self {
+            count: 32,
+            min_frequency: 20.0,
+            max_frequency: 20_000.0,
+        }
+    }
+}
+
+/// Settings of the spectrum analysis.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpectrumSettings {
+    /// Number of samples the FFT is computed over. Must be a power of two. Larger sizes give a
+    /// better frequency resolution, but a worse time resolution.
+    pub size: usize,
+    /// Window function applied to the samples before computing the FFT.
+    pub window: WindowFunction,
+    /// Optionally group the FFT bins into logarithmically spaced bands. When `None`, the
+    /// magnitudes of the `size / 2 + 1` bins are exposed directly.
+    pub bands: Option<SpectrumBands>,
+}
+
+impl Default for SpectrumSettings {
+    fn default() -> Self {
+        Self {
+            size: 1024,
+            window: WindowFunction::default(),
+            bands: None,
+        }
+    }
+}
+
+/// Component computing the spectrum of the audio going through an audio source or a track.
+///
+/// Insert it along with the [`AudioBundle`](crate::prelude::AudioBundle) or
+/// [`AudioTrackBundle`](crate::prelude::AudioTrackBundle), as the analysis needs to tap into the
+/// signal path when the source or track is created. Inserting it afterwards has no effect.
+///
+/// The spectrum is computed in the background on the [`AsyncComputeTaskPool`], and the latest
+/// available result is exposed through [`Self::magnitudes`].
+#[derive(Component)]
+pub struct AudioSpectrum {
+    settings: SpectrumSettings,
+    magnitudes: Vec<f32>,
+    sample_rate: u32,
+    analyzer: Option<SpectrumAnalyzer>,
+}
+
+impl Default for AudioSpectrum {
+    fn default() -> Self {
+        Self::new(SpectrumSettings::default()).expect("Default spectrum settings are valid")
+    }
+}
+
+impl AudioSpectrum {
+    /// Creates a new spectrum analysis with the given settings.
+    ///
+    /// Returns an error if the size is not a power of two, or if the bands are invalid (see
+    /// [`SpectrumBands`]).
+    pub fn new(settings: SpectrumSettings) -> Result<Self, AnalysisSettingsError> {
+        if !settings.size.is_power_of_two() {
+            return Err(AnalysisSettingsError::FftSizeNotPowerOfTwo(settings.size));
+        }
+        if let Some(bands) = settings.bands {
+            // Also rejects NaNs
+            let valid = bands.count > 0
+                && bands.min_frequency > 0.0
+                && bands.max_frequency > bands.min_frequency
+                && bands.max_frequency.is_finite();
+            if !valid {
+                return Err(AnalysisSettingsError::InvalidBands(bands));
+            }
+        }
+        Ok(Self {
+            settings,
+            magnitudes: vec![],
+            sample_rate: 0,
+            analyzer: None,
+        })
+    }
+
+    /// Settings of this spectrum analysis.
+    pub fn settings(&self) -> &SpectrumSettings {
+        &self.settings
+    }
+
+    /// Latest computed magnitudes, as linear amplitudes. Depending on the settings, these are
+    /// either the FFT bins or the frequency bands. This is empty until enough audio has been
+    /// processed.
+    pub fn magnitudes(&self) -> &[f32] {
+        &self.magnitudes
+    }
+
+    /// Center frequency (in Hz) of the magnitude at the given index. Returns `None` before audio
+    /// has started processing.
+    pub fn frequency(&self, index: usize) -> Option<f32> {
+        if self.sample_rate == 0 {
+            return None;
+        }
+        Some(match self.settings.bands {
+            Some(bands) => {
+                let (low, high) = band_range(&bands, index);
+                (low * high).sqrt()
+            }
+            None => index as f32 * self.sample_rate as f32 / self.settings.size as f32,
+        })
+    }
+}
+
+/// Returns the frequency range of the band at the given index.
+fn band_range(bands: &SpectrumBands, index: usize) -> (f32, f32) {
+    let ratio = bands.max_frequency / bands.min_frequency;
+    let frequency = |i: usize| bands.min_frequency * ratio.powf(i as f32 / bands.count as f32);
+    (frequency(index), frequency(index + 1))
+}
+
+/// Internal state of the analysis, created when the signal gets tapped.
+struct SpectrumAnalyzer {
+    receiver: TapReceiver,
+    samples: Vec<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    window: Arc<[f32]>,
+    /// Result of the FFT running on the task pool. Results are passed through a shared cell
+    /// rather than awaiting the task, which cannot be done without Bevy's `multi_threaded`
+    /// feature.
+    task: Option<Arc<OnceLock<Vec<f32>>>>,
+}
+
+pub(super) fn add_spectrums(
+    mut commands: Commands,
+    mut q: Query<(Entity, &mut AudioSpectrum), Added<AudioSpectrum>>,
+) {
+    for (entity, mut spectrum) in &mut q {
+        let size = spectrum.settings.size;
+        // Leave room for a few frames worth of audio
+        let (effect, receiver) = tap(size.max(8192) * 4);
+        spectrum.analyzer = Some(SpectrumAnalyzer {
+            receiver,
+            samples: Vec::with_capacity(size * 2),
+            fft: FftPlanner::new().plan_fft_forward(size),
+            window: spectrum.settings.window.coefficients(size).into(),
+            task: None,
+        });
+        PendingEffects::push(&mut commands, entity, Box::new(effect));
+    }
+}
+
+pub(super) fn update_spectrums(mut q: Query<&mut AudioSpectrum>) {
+    for mut spectrum in &mut q {
+        let spectrum = &mut *spectrum;
+        let Some(analyzer) = spectrum.analyzer.as_mut() else {
+            continue;
+        };
+
+        if let Some(task) = &analyzer.task {
+            if let Some(magnitudes) = task.get().cloned() {
+                spectrum.magnitudes = match spectrum.settings.bands {
+                    Some(bands) => {
+                        group_bands(&magnitudes, &bands, analyzer.receiver.sample_rate())
+                    }
+                    None => magnitudes,
+                };
+                spectrum.sample_rate = analyzer.receiver.sample_rate();
+                analyzer.task = None;
+            }
+        }
+
+        let size = spectrum.settings.size;
+        analyzer.receiver.receive_into(&mut analyzer.samples);
+        if analyzer.samples.len() > size {
+            let excess = analyzer.samples.len() - size;
+            analyzer.samples.drain(..excess);
+        }
+        if analyzer.task.is_some() || analyzer.samples.len() < size {
+            continue;
+        }
+
+        let mut buffer = analyzer
+            .samples
+            .iter()
+            .zip(analyzer.window.iter())
+            .map(|(sample, window)| Complex::new(sample * window, 0.0))
+            .collect::<Vec<_>>();
+        let fft = analyzer.fft.clone();
+        let window = analyzer.window.clone();
+        let result = Arc::new(OnceLock::new());
+        analyzer.task = Some(result.clone());
+        AsyncComputeTaskPool::get()
+            .spawn(async move {
+                fft.process(&mut buffer);
+                // Normalize so that a full-scale sine wave has a magnitude of 1
+                let scale = 2.0 / window.iter().sum::<f32>();
+                let magnitudes = buffer[..=size / 2]
+                    .iter()
+                    .map(|bin| bin.norm() * scale)
+                    .collect();
+                let _ = result.set(magnitudes);
+            })
+            .detach();
+    }
+}
+
+/// Groups FFT bins into logarithmically spaced bands, keeping the highest magnitude of each band.
+fn group_bands(bins: &[f32], bands: &SpectrumBands, sample_rate: u32) -> Vec<f32> {
+    if sample_rate == 0 || bins.len() < 2 {
+        return vec![0.0; bands.count];
+    }
+    let bin_width = sample_rate as f32 / (2 * (bins.len() - 1)) as f32;
+    let bin_index = |frequency: f32| ((frequency / bin_width) as usize).min(bins.len() - 1);
+    (0..bands.count)
+        .map(|index| {
+            let (low, high) = band_range(bands, index);
+            let (start, end) = (bin_index(low), bin_index(high));
+            // Low bands can be narrower than a single bin
+            bins[start..=end.max(start)]
+                .iter()
+                .copied()
+                .fold(0.0, f32::max)
+        })
+        .collect()
+}
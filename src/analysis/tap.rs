@@ -0,0 +1,71 @@
+//! Signal tap, copying the audio going through a source or track to a ring buffer, to be analyzed
+//! outside of the audio thread.
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use kira::clock::clock_info::ClockInfoProvider;
+use kira::effect::Effect;
+use kira::modulator::value_provider::ModulatorValueProvider;
+use kira::Frame;
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+
+/// Receiving end of a signal tap. Receives the signal downmixed to mono.
+pub(crate) struct TapReceiver {
+    samples: HeapConsumer<f32>,
+    sample_rate: Arc<AtomicU32>,
+}
+
+impl TapReceiver {
+    /// Sample rate of the received signal. This is zero until audio has started processing.
+    pub(crate) fn sample_rate(&self) -> u32 {
+        self.sample_rate.load(Ordering::Relaxed)
+    }
+
+    /// Moves all the received samples at the end of the given buffer.
+    pub(crate) fn receive_into(&mut self, buffer: &mut Vec<f32>) {
+        buffer.extend(self.samples.pop_iter());
+    }
+}
+
+/// Creates a signal tap effect, able to buffer up to `capacity` samples before dropping them.
+pub(crate) fn tap(capacity: usize) -> (TapEffect, TapReceiver) {
+    let (producer, consumer) = HeapRb::new(capacity).split();
+    let sample_rate = Arc::new(AtomicU32::new(0));
+    let effect = TapEffect {
+        samples: producer,
+        sample_rate: sample_rate.clone(),
+    };
+    let receiver = TapReceiver {
+        samples: consumer,
+        sample_rate,
+    };
+    (effect, receiver)
+}
+
+/// Effect copying the audio passing through it, without modifying it.
+pub(crate) struct TapEffect {
+    samples: HeapProducer<f32>,
+    sample_rate: Arc<AtomicU32>,
+}
+
+impl Effect for TapEffect {
+    fn init(&mut self, sample_rate: u32) {
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+    }
+
+    fn on_change_sample_rate(&mut self, sample_rate: u32) {
+        self.init(sample_rate);
+    }
+
+    fn process(
+        &mut self,
+        input: Frame,
+        _dt: f64,
+        _clock_info_provider: &ClockInfoProvider,
+        _modulator_value_provider: &ModulatorValueProvider,
+    ) -> Frame {
+        // Samples are dropped when the receiving end does not keep up
+        let _ = self.samples.push((input.left + input.right) / 2.0);
+        input
+    }
+}
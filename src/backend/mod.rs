@@ -1,15 +1,26 @@
 use std::fmt;
 use std::fmt::Formatter;
 
-use bevy::prelude::*;
 use cpal::traits::DeviceTrait;
-use kira::manager::backend::cpal::{CpalBackend, CpalBackendSettings};
 use kira::manager::backend::mock::{MockBackend, MockBackendSettings};
 use kira::manager::backend::{Backend, Renderer};
 use thiserror::Error;
 
 pub use cpal::*;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub use physical::*;
+pub use surround::ChannelLayout;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod physical;
+pub(crate) mod surround;
+
+/// Physical backend, outputting audio through `cpal`. Statistics about the audio callback are not
+/// available on the web.
+#[cfg(target_arch = "wasm32")]
+pub type PhysicalBackend = kira::manager::backend::cpal::CpalBackend;
+
 /// Allows the user to select an audio backend.
 ///
 /// The default backend uses physical audio devices for output, but there is an alternative "mock" backend that creates
@@ -27,6 +38,10 @@ pub enum AudioBackendSelector {
         /// data in chunks. This field controls whether you request a specific size for that chunk.
         /// The audio device is not required to honor that request.
         buffer_size: cpal::BufferSize,
+        /// Layout of the speakers to output to. Surround layouts need a device exposing enough
+        /// channels, and fall back to stereo otherwise; on those, spatial emitters are panned
+        /// across all the speakers. Surround output is not supported on the web.
+        channel_layout: ChannelLayout,
     },
     /// Mock audio backend, used to allow the audio engine to run even when no audio outputs are present on the device.
     ///
@@ -43,6 +58,7 @@ impl fmt::Debug for AudioBackendSelector {
             AudioBackendSelector::Physical {
                 device,
                 buffer_size,
+                channel_layout,
             } => f
                 .debug_struct(stringify!(AudioBackendSelector::Physical))
                 .field(
@@ -52,6 +68,7 @@ impl fmt::Debug for AudioBackendSelector {
                         .map(|device| device.name().unwrap_or("Unknown device name".to_string())),
                 )
                 .field("buffer_size", buffer_size)
+                .field("channel_layout", channel_layout)
                 .finish(),
             AudioBackendSelector::Mock { sample_rate } => f
                 .debug_struct(stringify!(AudioBackendSelector::Mock))
@@ -66,6 +83,7 @@ impl Default for AudioBackendSelector {
         Self::Physical {
             device: None,
             buffer_size: cpal::BufferSize::Default,
+            channel_layout: ChannelLayout::default(),
         }
     }
 }
@@ -81,11 +99,34 @@ pub enum AudioBackendError {
 /// Audio backend enum.
 pub enum AudioBackend {
     /// Physical backend that connects the audio engine to an actual audio output
-    Physical(CpalBackend),
+    Physical(PhysicalBackend),
     /// Mock backend which provides ways to manually drive the output stream
     Mock(Box<MockBackend>),
 }
 
+impl AudioBackend {
+    /// Statistics about the audio callback, if the backend supports measuring them.
+    ///
+    /// Only the physical backend on desktop platforms measures these, as the mock backend is
+    /// driven manually.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn statistics(&self) -> Option<std::sync::Arc<BackendStatistics>> {
+        match self {
+            Self::Physical(backend) => Some(backend.statistics().clone()),
+            Self::Mock(_) => None,
+        }
+    }
+
+    /// Bus of the surround channels, when the backend outputs to a surround speaker layout.
+    pub(crate) fn surround(&self) -> Option<std::sync::Arc<surround::SurroundBus>> {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            Self::Physical(backend) => backend.surround().cloned(),
+            _ => None,
+        }
+    }
+}
+
 impl Backend for AudioBackend {
     type Settings = AudioBackendSelector;
     type Error = AudioBackendError;
@@ -95,11 +136,19 @@ impl Backend for AudioBackend {
             AudioBackendSelector::Physical {
                 device,
                 buffer_size,
+                channel_layout,
             } => {
-                let (backend, sample_rate) = CpalBackend::setup(CpalBackendSettings {
-                    device,
-                    buffer_size,
-                })?;
+                #[cfg(not(target_arch = "wasm32"))]
+                let (backend, sample_rate) =
+                    PhysicalBackend::setup(device, buffer_size, channel_layout)?;
+                #[cfg(target_arch = "wasm32")]
+                let _ = channel_layout;
+                #[cfg(target_arch = "wasm32")]
+                let (backend, sample_rate) =
+                    PhysicalBackend::setup(kira::manager::backend::cpal::CpalBackendSettings {
+                        device,
+                        buffer_size,
+                    })?;
                 Ok((Self::Physical(backend), sample_rate))
             }
             AudioBackendSelector::Mock { sample_rate } => {
@@ -0,0 +1,481 @@
+//! Physical audio backend, outputting audio to an actual audio device through `cpal`.
+//!
+//! This is a reimplementation of Kira's own `cpal` backend, which additionally measures the time
+//! spent rendering audio in the audio callback, so that it can be reported back to Bevy (see
+//! [`BackendStatistics`]).
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{
+    BufferSize, Device, OutputCallbackInfo, Stream, StreamConfig, StreamError, StreamInstant,
+};
+use kira::manager::backend::cpal::Error;
+use kira::manager::backend::Renderer;
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+
+use super::surround::{ChannelLayout, SurroundBus};
+
+/// Interval at which the stream is checked for device disconnections or changes.
+const CHECK_STREAM_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Callbacks arriving later than this factor times the duration of the previous buffer are
+/// counted as underruns.
+const UNDERRUN_THRESHOLD: f64 = 1.5;
+
+/// Statistics about the audio callback, written by the audio thread and read from Bevy.
+///
+/// All values are stored in atomics, so that the audio thread never has to wait on (or allocate
+/// for) the reader.
+#[derive(Debug, Default)]
+pub struct BackendStatistics {
+    processing_nanos: AtomicU64,
+    period_nanos: AtomicU64,
+    peak_load: AtomicU32,
+    underruns: AtomicU64,
+    overruns: AtomicU64,
+}
+
+/// DSP load measured over a span of audio callbacks.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DspLoad {
+    /// Average fraction of the buffer period spent rendering audio.
+    pub average: f32,
+    /// Highest fraction of the buffer period spent rendering audio in a single callback.
+    pub peak: f32,
+}
+
+impl BackendStatistics {
+    /// Takes the DSP load accumulated since the last call to this method, and resets it. Returns
+    /// `None` when no audio callback happened since the last call.
+    ///
+    /// A load of `1.0` means that rendering the audio took exactly as long as playing it back;
+    /// anything above that results in audible glitches.
+    pub fn take_load(&self) -> Option<DspLoad> {
+        let period = self.period_nanos.swap(0, Ordering::Relaxed);
+        let processing = self.processing_nanos.swap(0, Ordering::Relaxed);
+        let peak = f32::from_bits(self.peak_load.swap(0, Ordering::Relaxed));
+        (period > 0).then(|| DspLoad {
+            average: (processing as f64 / period as f64) as f32,
+            peak,
+        })
+    }
+
+    /// Total number of buffer underruns since the stream started, that is, the number of times
+    /// the audio device called back later than expected, starving the output.
+    pub fn underruns(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    /// Total number of buffer overruns since the stream started, that is, the number of times
+    /// rendering a buffer took longer than the duration of that buffer.
+    pub fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, processing: Duration, period: Duration) {
+        let load = (processing.as_secs_f64() / period.as_secs_f64()) as f32;
+        self.processing_nanos
+            .fetch_add(processing.as_nanos() as u64, Ordering::Relaxed);
+        self.period_nanos
+            .fetch_add(period.as_nanos() as u64, Ordering::Relaxed);
+        // Only the audio thread writes to the peak value, but the reader may reset it in between
+        let _ = self
+            .peak_load
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                (load > f32::from_bits(bits)).then_some(load.to_bits())
+            });
+        if processing > period {
+            self.overruns.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn record_underrun(&self) {
+        self.underruns.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+enum State {
+    Empty,
+    Uninitialized {
+        device: Device,
+        config: StreamConfig,
+    },
+    Initialized {
+        should_drop: Arc<AtomicBool>,
+    },
+}
+
+/// Backend outputting audio to a physical audio device through `cpal`.
+pub struct PhysicalBackend {
+    state: State,
+    /// Whether the device was specified by the user.
+    custom_device: bool,
+    buffer_size: BufferSize,
+    channel_layout: ChannelLayout,
+    statistics: Arc<BackendStatistics>,
+    surround: Option<Arc<SurroundBus>>,
+}
+
+impl PhysicalBackend {
+    pub(crate) fn setup(
+        device: Option<Device>,
+        buffer_size: BufferSize,
+        channel_layout: ChannelLayout,
+    ) -> Result<(Self, u32), Error> {
+        let custom_device = device.is_some();
+        let device = match device {
+            Some(device) => device,
+            None => cpal::default_host()
+                .default_output_device()
+                .ok_or(Error::NoDefaultOutputDevice)?,
+        };
+        let config = output_config(&device, channel_layout)?;
+        let sample_rate = config.sample_rate.0;
+        // Spatial sounds keep being panned in stereo when the device has too few channels
+        let surround = (channel_layout != ChannelLayout::Stereo
+            && config.channels >= channel_layout.channels())
+        .then(|| Arc::new(SurroundBus::new(channel_layout)));
+        Ok((
+            Self {
+                state: State::Uninitialized { device, config },
+                custom_device,
+                buffer_size,
+                channel_layout,
+                statistics: Arc::default(),
+                surround,
+            },
+            sample_rate,
+        ))
+    }
+
+    pub(crate) fn start(&mut self, renderer: Renderer) -> Result<(), Error> {
+        let State::Uninitialized { device, config } =
+            std::mem::replace(&mut self.state, State::Empty)
+        else {
+            panic!("Cannot initialize the backend multiple times");
+        };
+        let should_drop = Arc::new(AtomicBool::new(false));
+        let (result_sender, result_receiver) = mpsc::sync_channel(1);
+        let custom_device = self.custom_device;
+        let buffer_size = self.buffer_size;
+        let channel_layout = self.channel_layout;
+        let statistics = self.statistics.clone();
+        let surround = self.surround.clone();
+        std::thread::spawn({
+            let should_drop = should_drop.clone();
+            // The stream manager is created in the thread that owns it, as the stream is not
+            // `Send` on all platforms
+            move || {
+                let mut stream_manager = StreamManager {
+                    state: StreamState::Idle { renderer },
+                    device_name: device_name(&device),
+                    sample_rate: config.sample_rate.0,
+                    custom_device,
+                    buffer_size,
+                    channel_layout,
+                    statistics,
+                    surround,
+                };
+                let result = stream_manager.start_stream(&device, config);
+                let failed = result.is_err();
+                let _ = result_sender.send(result);
+                if failed {
+                    return;
+                }
+                loop {
+                    std::thread::sleep(CHECK_STREAM_INTERVAL);
+                    if should_drop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    stream_manager.check_stream();
+                }
+            }
+        });
+        result_receiver
+            .recv()
+            .expect("Audio stream thread exited unexpectedly")?;
+        self.state = State::Initialized { should_drop };
+        Ok(())
+    }
+
+    /// Statistics about the audio callback of this backend.
+    pub fn statistics(&self) -> &Arc<BackendStatistics> {
+        &self.statistics
+    }
+
+    /// Bus of the channels other than the front left and right ones, when outputting to a
+    /// surround speaker layout.
+    pub(crate) fn surround(&self) -> Option<&Arc<SurroundBus>> {
+        self.surround.as_ref()
+    }
+}
+
+impl Drop for PhysicalBackend {
+    fn drop(&mut self) {
+        if let State::Initialized { should_drop } = &self.state {
+            should_drop.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+enum StreamState {
+    Empty,
+    Idle {
+        renderer: Renderer,
+    },
+    Running {
+        // Kept alive for the audio to keep playing
+        _stream: Stream,
+        stream_error_consumer: HeapConsumer<StreamError>,
+        renderer_consumer: HeapConsumer<Renderer>,
+    },
+}
+
+/// Owns the `cpal` stream and restarts it in the case of device changes or disconnections.
+struct StreamManager {
+    state: StreamState,
+    device_name: String,
+    sample_rate: u32,
+    custom_device: bool,
+    buffer_size: BufferSize,
+    channel_layout: ChannelLayout,
+    statistics: Arc<BackendStatistics>,
+    surround: Option<Arc<SurroundBus>>,
+}
+
+impl StreamManager {
+    /// Restarts the stream if the audio device gets disconnected, or if the default device
+    /// changed and no device was explicitly selected.
+    fn check_stream(&mut self) {
+        let stream_error_consumer = match &mut self.state {
+            StreamState::Running {
+                stream_error_consumer,
+                ..
+            } => stream_error_consumer,
+            // A previous attempt at starting the stream failed, try again with the default device
+            StreamState::Idle { .. } => {
+                if let Some((device, config)) = default_device_and_config(self.channel_layout) {
+                    let _ = self.start_stream(&device, config);
+                }
+                return;
+            }
+            StreamState::Empty => return,
+        };
+        if let Some(StreamError::DeviceNotAvailable) = stream_error_consumer.pop() {
+            self.stop_stream();
+            if let Some((device, config)) = default_device_and_config(self.channel_layout) {
+                if let Err(err) = self.start_stream(&device, config) {
+                    bevy::log::error!("Cannot restart audio stream: {err}");
+                }
+            }
+            return;
+        }
+        // Disabled on macOS due to audio artifacts that seem to occur when the device is queried
+        // while playing (see https://github.com/tesselode/kira/issues/38)
+        #[cfg(not(target_os = "macos"))]
+        if !self.custom_device {
+            if let Some((device, config)) = default_device_and_config(self.channel_layout) {
+                if device_name(&device) != self.device_name
+                    || config.sample_rate.0 != self.sample_rate
+                {
+                    self.stop_stream();
+                    if let Err(err) = self.start_stream(&device, config) {
+                        bevy::log::error!("Cannot restart audio stream: {err}");
+                    }
+                }
+            }
+        }
+    }
+
+    fn start_stream(&mut self, device: &Device, mut config: StreamConfig) -> Result<(), Error> {
+        let StreamState::Idle { mut renderer } =
+            std::mem::replace(&mut self.state, StreamState::Empty)
+        else {
+            panic!("Trying to start a stream when the stream manager is not idle");
+        };
+        // This doesn't change anything if the buffer size is `BufferSize::Default`
+        config.buffer_size = self.buffer_size;
+        let sample_rate = config.sample_rate.0;
+        if sample_rate != self.sample_rate {
+            renderer.on_change_sample_rate(sample_rate);
+        }
+        self.device_name = device_name(device);
+        self.sample_rate = sample_rate;
+
+        let (mut renderer, mut renderer_consumer) = RendererWrapper::new(renderer);
+        let (mut stream_error_producer, stream_error_consumer) = HeapRb::new(1).split();
+        let channels = config.channels as usize;
+        let statistics = self.statistics.clone();
+        let surround = self.surround.clone();
+        let mut last_callback: Option<(StreamInstant, Duration)> = None;
+        let result = device.build_output_stream(
+            &config,
+            move |data: &mut [f32], info: &OutputCallbackInfo| {
+                let start = Instant::now();
+                process_renderer(&mut renderer, data, channels, surround.as_deref());
+                let processing = start.elapsed();
+
+                let period =
+                    Duration::from_secs_f64((data.len() / channels) as f64 / sample_rate as f64);
+                // Empty buffers carry no timing information, and would make the load infinite
+                if period.is_zero() {
+                    return;
+                }
+                let callback = info.timestamp().callback;
+                if let Some((last, last_period)) = last_callback {
+                    let late = callback.duration_since(&last).is_some_and(|gap| {
+                        gap.as_secs_f64() > UNDERRUN_THRESHOLD * last_period.as_secs_f64()
+                    });
+                    if late {
+                        statistics.record_underrun();
+                    }
+                }
+                last_callback = Some((callback, period));
+                statistics.record(processing, period);
+            },
+            move |error| {
+                // Only the first error matters to the stream manager
+                let _ = stream_error_producer.push(error);
+            },
+            None,
+        );
+        let stream = match result.map_err(Error::from).and_then(|stream| {
+            stream.play()?;
+            Ok(stream)
+        }) {
+            Ok(stream) => stream,
+            Err(err) => {
+                // The callback has been dropped, which sent the renderer back
+                let renderer = renderer_consumer
+                    .pop()
+                    .expect("Could not retrieve the renderer after failing to start a stream");
+                self.state = StreamState::Idle { renderer };
+                return Err(err);
+            }
+        };
+        self.state = StreamState::Running {
+            _stream: stream,
+            stream_error_consumer,
+            renderer_consumer,
+        };
+        Ok(())
+    }
+
+    fn stop_stream(&mut self) {
+        let StreamState::Running {
+            _stream: stream,
+            mut renderer_consumer,
+            ..
+        } = std::mem::replace(&mut self.state, StreamState::Empty)
+        else {
+            panic!("Trying to stop the stream when it's not running");
+        };
+        drop(stream);
+        let renderer = renderer_consumer
+            .pop()
+            .expect("Could not retrieve the renderer after dropping a stream");
+        self.state = StreamState::Idle { renderer };
+    }
+}
+
+/// Wraps a [`Renderer`] so that when it's dropped (along with the stream callback owning it), it
+/// gets sent back to the stream manager, to be reused in a new stream.
+struct RendererWrapper {
+    renderer: Option<Renderer>,
+    producer: HeapProducer<Renderer>,
+}
+
+impl RendererWrapper {
+    fn new(renderer: Renderer) -> (Self, HeapConsumer<Renderer>) {
+        let (producer, consumer) = HeapRb::new(1).split();
+        (
+            Self {
+                renderer: Some(renderer),
+                producer,
+            },
+            consumer,
+        )
+    }
+}
+
+impl Drop for RendererWrapper {
+    fn drop(&mut self) {
+        if let Some(renderer) = self.renderer.take() {
+            if self.producer.push(renderer).is_err() {
+                panic!("The renderer producer is full");
+            }
+        }
+    }
+}
+
+fn process_renderer(
+    wrapper: &mut RendererWrapper,
+    data: &mut [f32],
+    channels: usize,
+    surround: Option<&SurroundBus>,
+) {
+    let renderer = wrapper.renderer.as_mut().unwrap();
+    renderer.on_start_processing();
+    for frame in data.chunks_exact_mut(channels) {
+        let out = renderer.process();
+        if channels == 1 {
+            frame[0] = (out.left + out.right) / 2.0;
+        } else {
+            frame[0] = out.left;
+            frame[1] = out.right;
+            // Send silence to the other channels, to prevent garbage from being output there
+            for channel in frame.iter_mut().skip(2) {
+                *channel = 0.0;
+            }
+        }
+        if let Some(surround) = surround {
+            surround.mix_into(frame);
+        }
+    }
+}
+
+/// Output configuration of the device for the channel layout, at its default sample rate. Falls
+/// back to the default configuration of the device when it doesn't support the layout.
+fn output_config(device: &Device, channel_layout: ChannelLayout) -> Result<StreamConfig, Error> {
+    let default = device.default_output_config()?.config();
+    let channels = channel_layout.channels();
+    if channels <= default.channels {
+        return Ok(default);
+    }
+    let config = device
+        .supported_output_configs()
+        .ok()
+        .and_then(|mut configs| {
+            configs.find(|config| {
+                config.channels() == channels
+                    && config.min_sample_rate() <= default.sample_rate
+                    && config.max_sample_rate() >= default.sample_rate
+            })
+        });
+    match config {
+        Some(config) => Ok(config.with_sample_rate(default.sample_rate).config()),
+        None => {
+            bevy::log::warn!(
+                "Audio device {} does not support {channels} channels, using {} channels",
+                device_name(device),
+                default.channels
+            );
+            Ok(default)
+        }
+    }
+}
+
+fn default_device_and_config(channel_layout: ChannelLayout) -> Option<(Device, StreamConfig)> {
+    let device = cpal::default_host().default_output_device()?;
+    let config = output_config(&device, channel_layout).ok()?;
+    Some((device, config))
+}
+
+fn device_name(device: &Device) -> String {
+    device
+        .name()
+        .unwrap_or_else(|_| "device name unavailable".to_string())
+}
@@ -0,0 +1,150 @@
+//! Surround output, for speaker layouts with more channels than the stereo output of Kira.
+//!
+//! Kira mixes everything in stereo, which only feeds the front left and right speakers. The other
+//! speakers of the layout are fed by the sounds of spatial emitters, which pan themselves around
+//! the speakers and add what goes to the other speakers to a [`SurroundBus`], mixed into the
+//! output by the backend after Kira has rendered the frame. These channels are therefore not
+//! processed by the effects of the main track, and its volume is applied by the emitters.
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Layout of the speakers the audio is output to.
+///
+/// Kira only renders the front left and right speakers of surround layouts. The other speakers
+/// are fed by spatial emitters directly, outside of the mixer of Kira, which has a few
+/// limitations:
+///
+/// - They do not go through the effects of the mixer tracks, including the environment reverb
+///   and the effects of the main track.
+/// - The volume of the main track only applies to them when set with
+///   [`AudioWorld::set_main_volume`](crate::AudioWorld::set_main_volume), and not with the
+///   builder of the main track.
+/// - Their distance attenuation is computed once per frame and smoothed, instead of following the
+///   emitter within the frame like Kira does.
+///
+/// Non-spatial sounds only play on the front speakers.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ChannelLayout {
+    /// Left and right speakers (or headphones). This is the default.
+    #[default]
+    Stereo,
+    /// 5.1 surround: front left and right, center, LFE, side left and right.
+    Surround51,
+    /// 7.1 surround: front left and right, center, LFE, back left and right, side left and right.
+    Surround71,
+}
+
+impl ChannelLayout {
+    /// Number of channels of the layout.
+    pub fn channels(&self) -> u16 {
+        match self {
+            Self::Stereo => 2,
+            Self::Surround51 => 6,
+            Self::Surround71 => 8,
+        }
+    }
+
+    /// Speakers sounds are panned between, sorted by azimuth (the LFE is left out).
+    pub(crate) fn speakers(&self) -> &'static [Speaker] {
+        const fn speaker(channel: usize, azimuth: f32) -> Speaker {
+            Speaker { channel, azimuth }
+        }
+        const STEREO: &[Speaker] = &[speaker(FRONT_RIGHT, -90.0), speaker(FRONT_LEFT, 90.0)];
+        const SURROUND_51: &[Speaker] = &[
+            speaker(5, -110.0),
+            speaker(FRONT_RIGHT, -30.0),
+            speaker(2, 0.0),
+            speaker(FRONT_LEFT, 30.0),
+            speaker(4, 110.0),
+        ];
+        const SURROUND_71: &[Speaker] = &[
+            speaker(5, -150.0),
+            speaker(7, -90.0),
+            speaker(FRONT_RIGHT, -30.0),
+            speaker(2, 0.0),
+            speaker(FRONT_LEFT, 30.0),
+            speaker(6, 90.0),
+            speaker(4, 150.0),
+        ];
+        match self {
+            Self::Stereo => STEREO,
+            Self::Surround51 => SURROUND_51,
+            Self::Surround71 => SURROUND_71,
+        }
+    }
+}
+
+/// Channel of the front left speaker, fed by Kira.
+pub(crate) const FRONT_LEFT: usize = 0;
+/// Channel of the front right speaker, fed by Kira.
+pub(crate) const FRONT_RIGHT: usize = 1;
+/// Highest number of channels of the supported layouts.
+pub(crate) const MAX_CHANNELS: usize = 8;
+
+/// Speaker of a [`ChannelLayout`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct Speaker {
+    /// Channel feeding the speaker
+    pub(crate) channel: usize,
+    /// Direction of the speaker, in degrees counter-clockwise from the front
+    pub(crate) azimuth: f32,
+}
+
+/// Channels of the current frame other than the front left and right ones, accumulated by the
+/// sounds of spatial emitters while Kira renders the frame.
+///
+/// Both the sounds and the backend run on the audio thread, one after the other; atomics only
+/// make the bus shareable.
+#[derive(Debug)]
+pub(crate) struct SurroundBus {
+    pub(crate) layout: ChannelLayout,
+    /// Samples stored as the bits of `f32`s
+    channels: [AtomicU32; MAX_CHANNELS],
+}
+
+impl SurroundBus {
+    pub(crate) fn new(layout: ChannelLayout) -> Self {
+        Self {
+            layout,
+            channels: Default::default(),
+        }
+    }
+
+    /// Adds the sample to the channel in the current frame.
+    pub(crate) fn add(&self, channel: usize, sample: f32) {
+        let channel = &self.channels[channel];
+        let value = f32::from_bits(channel.load(Ordering::Relaxed)) + sample;
+        channel.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Mixes the accumulated channels into the frame, where the front channels have already been
+    /// written, and clears them for the next frame. Frames with fewer channels than the layout
+    /// (after a switch to another device) get the missing channels folded into the front ones.
+    pub(crate) fn mix_into(&self, frame: &mut [f32]) {
+        let speakers = self.layout.speakers();
+        for (channel, value) in self.channels.iter().enumerate().skip(2) {
+            let sample = f32::from_bits(value.swap(0, Ordering::Relaxed));
+            if sample == 0.0 {
+                continue;
+            }
+            if let Some(output) = frame.get_mut(channel) {
+                *output += sample;
+                continue;
+            }
+            // Fold the speaker into the front ones, panned by its side
+            let Some(speaker) = speakers.iter().find(|speaker| speaker.channel == channel) else {
+                continue;
+            };
+            let panning = 0.5 - speaker.azimuth.to_radians().sin() / 2.0;
+            let left = (panning * std::f32::consts::FRAC_PI_2).cos();
+            let right = (panning * std::f32::consts::FRAC_PI_2).sin();
+            match frame {
+                [mono] => *mono += sample * (left + right) / 2.0,
+                [front_left, front_right, ..] => {
+                    *front_left += sample * left;
+                    *front_right += sample * right;
+                }
+                [] => {}
+            }
+        }
+    }
+}
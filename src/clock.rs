@@ -0,0 +1,105 @@
+//! Support for Kira clocks as entities.
+//!
+//! Adding an [`AudioClock`] component to an entity creates a Kira clock for it. Clocks tick at a
+//! given speed on the audio thread, and are used to schedule audio events with sample accuracy,
+//! e.g. to start music transitions on a beat (see
+//! [`CrossfadeSettings::sync`](crate::prelude::CrossfadeSettings::sync)).
+use bevy::prelude::*;
+use kira::clock::{ClockHandle, ClockSpeed, ClockTime};
+
+use crate::{AudioPlaybackSet, AudioSourceSetup, AudioWorld};
+
+#[doc(hidden)]
+#[allow(missing_docs)]
+pub mod prelude {
+    pub use super::{AudioClock, AudioClockHandle};
+}
+
+/// Clock plugin. This is an internal plugin, useful for some separation of concerns.
+///
+/// It is automatically added by the main [`AudioPlugin`](crate::AudioPlugin).
+pub(crate) struct AudioClockPlugin;
+
+impl Plugin for AudioClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            add_clocks
+                .in_set(AudioPlaybackSet::Update)
+                .before(AudioSourceSetup),
+        );
+    }
+}
+
+/// Component creating a clock for this entity.
+///
+/// Note that these settings are only used in the setup of the clock, and not kept in sync
+/// afterwards. Use the [`AudioClockHandle`] component to control the clock once created.
+#[derive(Debug, Component)]
+pub struct AudioClock {
+    /// Initial speed of the clock.
+    pub speed: ClockSpeed,
+    /// By default, clocks start ticking right away when created. Setting this to `true` prevents
+    /// that.
+    pub start_paused: bool,
+}
+
+impl Default for AudioClock {
+    fn default() -> Self {
+        Self::new(ClockSpeed::TicksPerMinute(120.0))
+    }
+}
+
+impl AudioClock {
+    /// Creates a clock ticking at the given speed.
+    pub fn new(speed: ClockSpeed) -> Self {
+        Self {
+            speed,
+            start_paused: false,
+        }
+    }
+}
+
+/// Component holding the handle to the Kira clock created for the [`AudioClock`] of this entity.
+/// Access this component from your systems to control the clock. Despawning the entity (or
+/// removing this component) removes the clock from the audio engine.
+#[derive(Debug, Deref, DerefMut, Component)]
+pub struct AudioClockHandle(pub ClockHandle);
+
+impl AudioClockHandle {
+    /// Returns the time of the next tick of this clock that is a multiple of `interval` ticks,
+    /// e.g. the start of the next bar when ticking on beats and `interval` is the number of beats
+    /// per bar.
+    pub fn next_multiple(&self, interval: u64) -> ClockTime {
+        let interval = interval.max(1);
+        let time = self.time();
+        let ticks = (time.ticks / interval + 1) * interval;
+        ClockTime::from_ticks_u64(time.clock, ticks)
+    }
+}
+
+fn add_clocks(
+    mut commands: Commands,
+    mut audio_world: ResMut<AudioWorld>,
+    q_added: Query<(Entity, &AudioClock), Without<AudioClockHandle>>,
+) {
+    for (entity, clock) in &q_added {
+        let manager = &audio_world.audio_manager;
+        if manager.num_clocks() >= manager.clock_capacity() {
+            warn_once!("Clock limit reached, clocks will be created once others are removed");
+            continue;
+        }
+        match audio_world.audio_manager.add_clock(clock.speed) {
+            Ok(mut handle) => {
+                if !clock.start_paused {
+                    handle.start();
+                }
+                debug!("Added clock in {entity:?}");
+                commands.entity(entity).insert(AudioClockHandle(handle));
+            }
+            Err(err) => {
+                error!("Cannot create clock for entity {entity:?}: {err}");
+            }
+        }
+    }
+}
@@ -1,5 +1,10 @@
 //! Register diagnostics regarding statistics of `Kira`'s audio engine usage.
 #![cfg(feature = "diagnostics")]
+use std::marker::PhantomData;
+
+use crate::sources::{AudioError, AudioHandle, AudioSource, SoundDropped};
+use crate::voices::virtualization::Virtualized;
+use crate::voices::VoiceStolen;
 use crate::AudioWorld;
 use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
 
@@ -10,34 +15,176 @@ pub struct KiraStatisticsDiagnosticPlugin;
 
 impl Plugin for KiraStatisticsDiagnosticPlugin {
     fn build(&self, app: &mut App) {
-        app.register_diagnostic(Diagnostic::new(COMMAND_COUNT).with_suffix(" commands"))
-            .register_diagnostic(Diagnostic::new(SOUND_COUNT).with_suffix(" sounds"))
+        app.register_diagnostic(Diagnostic::new(SOUND_COUNT).with_suffix(" sounds"))
+            .register_diagnostic(Diagnostic::new(SUB_TRACK_COUNT).with_suffix(" tracks"))
             .register_diagnostic(Diagnostic::new(CLOCK_COUNT).with_suffix(" clocks"))
             .register_diagnostic(Diagnostic::new(MODULATOR_COUNT).with_suffix(" modulators"))
             .register_diagnostic(
                 Diagnostic::new(SPATIAL_SCENE_COUNT).with_suffix(" spatial scenes"),
             )
+            .register_diagnostic(Diagnostic::new(SOUND_CAPACITY_USAGE).with_suffix("%"))
+            .register_diagnostic(Diagnostic::new(SUB_TRACK_CAPACITY_USAGE).with_suffix("%"))
+            .register_diagnostic(Diagnostic::new(CLOCK_CAPACITY_USAGE).with_suffix("%"))
+            .register_diagnostic(Diagnostic::new(MODULATOR_CAPACITY_USAGE).with_suffix("%"))
+            .register_diagnostic(Diagnostic::new(SPATIAL_SCENE_CAPACITY_USAGE).with_suffix("%"))
             .add_systems(Last, record_diagnostics);
+        #[cfg(not(target_arch = "wasm32"))]
+        app.register_diagnostic(Diagnostic::new(DSP_LOAD).with_suffix("%"))
+            .register_diagnostic(Diagnostic::new(DSP_PEAK_LOAD).with_suffix("%"))
+            .register_diagnostic(Diagnostic::new(UNDERRUNS).with_suffix(" underruns"))
+            .register_diagnostic(Diagnostic::new(OVERRUNS).with_suffix(" overruns"))
+            .add_systems(Last, record_backend_diagnostics);
     }
 }
 
-const COMMAND_COUNT: DiagnosticPath = DiagnosticPath::const_new("kira::manager::command_count");
-const SOUND_COUNT: DiagnosticPath = DiagnosticPath::const_new("kira::manager::sound_count");
-const CLOCK_COUNT: DiagnosticPath = DiagnosticPath::const_new("kira::manager::clock_count");
-const MODULATOR_COUNT: DiagnosticPath = DiagnosticPath::const_new("kira::manager::modulator_count");
-const SPATIAL_SCENE_COUNT: DiagnosticPath =
+/// Bevy diagnostic path recording the number of sounds currently playing in the audio engine.
+pub const SOUND_COUNT: DiagnosticPath = DiagnosticPath::const_new("kira::manager::sound_count");
+/// Bevy diagnostic path recording the number of sub-tracks (i.e. all tracks except the main one)
+/// present in the audio engine.
+pub const SUB_TRACK_COUNT: DiagnosticPath =
+    DiagnosticPath::const_new("kira::manager::sub_track_count");
+/// Bevy diagnostic path recording the number of clocks present in the audio engine.
+pub const CLOCK_COUNT: DiagnosticPath = DiagnosticPath::const_new("kira::manager::clock_count");
+/// Bevy diagnostic path recording the number of modulators present in the audio engine.
+pub const MODULATOR_COUNT: DiagnosticPath =
+    DiagnosticPath::const_new("kira::manager::modulator_count");
+/// Bevy diagnostic path recording the number of spatial scenes present in the audio engine.
+pub const SPATIAL_SCENE_COUNT: DiagnosticPath =
     DiagnosticPath::const_new("kira::manager::spatial_scene_count");
 
+/// Bevy diagnostic path recording the percentage of the sound capacity currently in use. Once
+/// this reaches 100%, new sounds fail to play with `PlaySoundError::SoundLimitReached`.
+pub const SOUND_CAPACITY_USAGE: DiagnosticPath =
+    DiagnosticPath::const_new("kira::manager::sound_capacity_usage");
+/// Bevy diagnostic path recording the percentage of the sub-track capacity currently in use.
+pub const SUB_TRACK_CAPACITY_USAGE: DiagnosticPath =
+    DiagnosticPath::const_new("kira::manager::sub_track_capacity_usage");
+/// Bevy diagnostic path recording the percentage of the clock capacity currently in use.
+pub const CLOCK_CAPACITY_USAGE: DiagnosticPath =
+    DiagnosticPath::const_new("kira::manager::clock_capacity_usage");
+/// Bevy diagnostic path recording the percentage of the modulator capacity currently in use.
+pub const MODULATOR_CAPACITY_USAGE: DiagnosticPath =
+    DiagnosticPath::const_new("kira::manager::modulator_capacity_usage");
+/// Bevy diagnostic path recording the percentage of the spatial scene capacity currently in use.
+pub const SPATIAL_SCENE_CAPACITY_USAGE: DiagnosticPath =
+    DiagnosticPath::const_new("kira::manager::spatial_scene_capacity_usage");
+
+/// Bevy diagnostic path recording the average percentage of the audio buffer period spent
+/// rendering audio since the last frame. Above 100%, the audio engine cannot keep up and the
+/// output glitches.
+pub const DSP_LOAD: DiagnosticPath = DiagnosticPath::const_new("kira::backend::dsp_load");
+/// Bevy diagnostic path recording the highest percentage of the audio buffer period spent
+/// rendering a single buffer since the last frame.
+pub const DSP_PEAK_LOAD: DiagnosticPath = DiagnosticPath::const_new("kira::backend::dsp_peak_load");
+/// Bevy diagnostic path recording the total number of buffer underruns, i.e. the number of times
+/// the audio device asked for audio later than expected.
+pub const UNDERRUNS: DiagnosticPath = DiagnosticPath::const_new("kira::backend::underruns");
+/// Bevy diagnostic path recording the total number of buffer overruns, i.e. the number of times
+/// rendering an audio buffer took longer than the duration of that buffer.
+pub const OVERRUNS: DiagnosticPath = DiagnosticPath::const_new("kira::backend::overruns");
+
+fn capacity_usage(count: u16, capacity: u16) -> f64 {
+    if capacity == 0 {
+        return 0.0;
+    }
+    100.0 * count as f64 / capacity as f64
+}
+
 fn record_diagnostics(audio_world: Res<AudioWorld>, mut diagnostics: Diagnostics) {
-    diagnostics.add_measurement(&COMMAND_COUNT, || {
-        audio_world.audio_manager.num_modulators() as _
+    let manager = &audio_world.audio_manager;
+    diagnostics.add_measurement(&SOUND_COUNT, || manager.num_sounds() as _);
+    diagnostics.add_measurement(&SUB_TRACK_COUNT, || manager.num_sub_tracks() as _);
+    diagnostics.add_measurement(&CLOCK_COUNT, || manager.num_clocks() as _);
+    diagnostics.add_measurement(&MODULATOR_COUNT, || manager.num_modulators() as _);
+    diagnostics.add_measurement(&SPATIAL_SCENE_COUNT, || manager.num_spatial_scenes() as _);
+
+    diagnostics.add_measurement(&SOUND_CAPACITY_USAGE, || {
+        capacity_usage(manager.num_sounds(), manager.sound_capacity())
+    });
+    diagnostics.add_measurement(&SUB_TRACK_CAPACITY_USAGE, || {
+        capacity_usage(manager.num_sub_tracks(), manager.sub_track_capacity())
     });
-    diagnostics.add_measurement(&SOUND_COUNT, || audio_world.audio_manager.num_sounds() as _);
-    diagnostics.add_measurement(&CLOCK_COUNT, || audio_world.audio_manager.num_clocks() as _);
-    diagnostics.add_measurement(&MODULATOR_COUNT, || {
-        audio_world.audio_manager.num_modulators() as _
+    diagnostics.add_measurement(&CLOCK_CAPACITY_USAGE, || {
+        capacity_usage(manager.num_clocks(), manager.clock_capacity())
     });
-    diagnostics.add_measurement(&SPATIAL_SCENE_COUNT, || {
-        audio_world.audio_manager.num_spatial_scenes() as _
+    diagnostics.add_measurement(&MODULATOR_CAPACITY_USAGE, || {
+        capacity_usage(manager.num_modulators(), manager.modulator_capacity())
     });
+    diagnostics.add_measurement(&SPATIAL_SCENE_CAPACITY_USAGE, || {
+        capacity_usage(
+            manager.num_spatial_scenes(),
+            manager.spatial_scene_capacity(),
+        )
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn record_backend_diagnostics(audio_world: Res<AudioWorld>, mut diagnostics: Diagnostics) {
+    let Some(statistics) = audio_world.backend_statistics() else {
+        return;
+    };
+    if let Some(load) = statistics.take_load() {
+        diagnostics.add_measurement(&DSP_LOAD, || 100.0 * load.average as f64);
+        diagnostics.add_measurement(&DSP_PEAK_LOAD, || 100.0 * load.peak as f64);
+    }
+    diagnostics.add_measurement(&UNDERRUNS, || statistics.underruns() as _);
+    diagnostics.add_measurement(&OVERRUNS, || statistics.overruns() as _);
+}
+
+/// Returns the Bevy diagnostic path recording the number of sounds of the given [`AudioSource`]
+/// type currently registered with the audio engine.
+pub fn source_sound_count<T: AudioSource>() -> DiagnosticPath {
+    DiagnosticPath::new(format!("kira::sources::{}::sounds", T::short_type_path()))
+}
+
+/// Returns the Bevy diagnostic path recording the number of sounds of the given [`AudioSource`]
+/// type which are waiting to be registered with the audio engine.
+pub fn source_pending_count<T: AudioSource>() -> DiagnosticPath {
+    DiagnosticPath::new(format!("kira::sources::{}::pending", T::short_type_path()))
+}
+
+/// Register diagnostics for a specific [`AudioSource`] type. This is automatically added by the
+/// [`AudioSourcePlugin`](crate::sources::AudioSourcePlugin) when the `diagnostics` feature is
+/// enabled.
+pub(crate) struct AudioSourceDiagnosticsPlugin<T>(PhantomData<T>);
+
+impl<T> Default for AudioSourceDiagnosticsPlugin<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: AudioSource> Plugin for AudioSourceDiagnosticsPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.register_diagnostic(Diagnostic::new(source_sound_count::<T>()).with_suffix(" sounds"))
+            .register_diagnostic(
+                Diagnostic::new(source_pending_count::<T>()).with_suffix(" sources"),
+            )
+            .add_systems(Last, Self::record_diagnostics);
+    }
+}
+
+impl<T: AudioSource> AudioSourceDiagnosticsPlugin<T> {
+    #[allow(clippy::type_complexity)]
+    fn record_diagnostics(
+        mut diagnostics: Diagnostics,
+        mut paths: Local<Option<(DiagnosticPath, DiagnosticPath)>>,
+        q_sounds: Query<(), With<AudioHandle<T::Handle>>>,
+        q_pending: Query<
+            (),
+            (
+                With<Handle<T>>,
+                Without<AudioHandle<T::Handle>>,
+                Without<AudioError>,
+                Without<VoiceStolen>,
+                Without<SoundDropped>,
+                Without<Virtualized>,
+            ),
+        >,
+    ) {
+        let (sound_count, pending_count) =
+            paths.get_or_insert_with(|| (source_sound_count::<T>(), source_pending_count::<T>()));
+        diagnostics.add_measurement(sound_count, || q_sounds.iter().count() as _);
+        diagnostics.add_measurement(pending_count, || q_pending.iter().count() as _);
+    }
 }
@@ -0,0 +1,196 @@
+//! Support for inserting effects into the signal path of audio sources and tracks.
+//!
+//! Kira only supports effects on mixer tracks, and spatial emitters cannot be fed from a track.
+//! To be able to process the audio of a single source regardless of where it is routed to, the
+//! sound itself is wrapped so that effects are applied to its output before Kira routes it.
+//!
+//! Effects are collected from other components (e.g. [`AudioMeter`](crate::prelude::AudioMeter))
+//! into the internal [`PendingEffects`] component, and consumed when the audio engine resource
+//! (sound or track) is created. Effects therefore need to be requested before that happens,
+//! usually by inserting the requesting component at the same time as the source or track.
+//!
+//! The same wrapper tracks the voice of the sound, for it to be stolen by voice limits (see
+//! [`crate::voices`]), and applies the effects of spatial emitters done per sound (see
+//! [`crate::spatial::doppler`], [`crate::spatial::occlusion`], [`crate::spatial::cone`],
+//! [`crate::spatial::absorption`], [`crate::spatial::surround`] and the binaural rendering).
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use kira::clock::clock_info::ClockInfoProvider;
+use kira::effect::Effect;
+use kira::modulator::value_provider::ModulatorValueProvider;
+use kira::sound::{Sound, SoundData};
+use kira::{Frame, OutputDestination};
+
+use crate::spatial::absorption::AirAbsorptionProcessor;
+#[cfg(feature = "hrtf")]
+use crate::spatial::binaural::BinauralProcessor;
+use crate::spatial::cone::ConeProcessor;
+use crate::spatial::doppler::DopplerProcessor;
+use crate::spatial::occlusion::OcclusionProcessor;
+use crate::spatial::surround::SurroundProcessor;
+use crate::spatial::EmitterEffects;
+use crate::voices::{VoiceProcessor, VoiceState};
+
+/// Internal component holding effects waiting to be inserted in the signal path of the audio
+/// source or track of this entity.
+#[derive(Component, Default)]
+pub(crate) struct PendingEffects(pub(crate) Vec<Box<dyn Effect>>);
+
+impl PendingEffects {
+    /// Queue a command pushing the effect to the pending effects of the given entity.
+    ///
+    /// This does not overwrite effects requested by other components on the same entity.
+    pub(crate) fn push(commands: &mut Commands, entity: Entity, effect: Box<dyn Effect>) {
+        commands.add(move |world: &mut World| {
+            let Some(mut entity) = world.get_entity_mut(entity) else {
+                return;
+            };
+            if let Some(mut effects) = entity.get_mut::<PendingEffects>() {
+                effects.0.push(effect);
+            } else {
+                entity.insert(PendingEffects(vec![effect]));
+            }
+        });
+    }
+}
+
+/// Wraps [`SoundData`] so that the created sound has the given effects applied to its output,
+/// is tracked as the given voice, and has the effects of its spatial emitter applied.
+pub(crate) struct SoundDataWithEffects<D> {
+    pub(crate) data: D,
+    pub(crate) effects: Vec<Box<dyn Effect>>,
+    pub(crate) voice: Option<Arc<VoiceState>>,
+    pub(crate) emitter: EmitterEffects,
+}
+
+impl<D: SoundData> SoundData for SoundDataWithEffects<D> {
+    type Error = D::Error;
+    type Handle = D::Handle;
+
+    fn into_sound(self) -> Result<(Box<dyn Sound>, Self::Handle), Self::Error> {
+        let (sound, handle) = self.data.into_sound()?;
+        let emitter = self.emitter;
+        if self.effects.is_empty() && self.voice.is_none() && emitter.is_empty() {
+            return Ok((sound, handle));
+        }
+        let sound = SoundWithEffects {
+            sound,
+            effects: self.effects,
+            voice: self.voice.map(VoiceProcessor::new),
+            doppler: emitter.doppler.map(DopplerProcessor::new),
+            occlusion: emitter.occlusion.map(OcclusionProcessor::new),
+            cone: emitter.cone.map(ConeProcessor::new),
+            air_absorption: emitter.air_absorption.map(AirAbsorptionProcessor::new),
+            #[cfg(feature = "hrtf")]
+            binaural: emitter.binaural.map(BinauralProcessor::new),
+            surround: emitter.surround.map(SurroundProcessor::new),
+            sample_rate: None,
+        };
+        Ok((Box::new(sound), handle))
+    }
+}
+
+/// Sound processing the output of another sound through a chain of effects, and tracking it as a
+/// voice.
+struct SoundWithEffects {
+    sound: Box<dyn Sound>,
+    effects: Vec<Box<dyn Effect>>,
+    voice: Option<VoiceProcessor>,
+    /// The Doppler effect is applied by advancing the sound faster or slower than real time
+    doppler: Option<DopplerProcessor>,
+    occlusion: Option<OcclusionProcessor>,
+    cone: Option<ConeProcessor>,
+    air_absorption: Option<AirAbsorptionProcessor>,
+    #[cfg(feature = "hrtf")]
+    binaural: Option<BinauralProcessor>,
+    /// Applied last, as the channels it sends to the surround bus bypass Kira
+    surround: Option<SurroundProcessor>,
+    /// Sample rate the effects have been initialized with. Kira does not give sounds access to
+    /// the sample rate, so it is derived from the time step on the first processed frame.
+    sample_rate: Option<u32>,
+}
+
+impl Sound for SoundWithEffects {
+    fn output_destination(&mut self) -> OutputDestination {
+        self.sound.output_destination()
+    }
+
+    fn on_start_processing(&mut self) {
+        self.sound.on_start_processing();
+        for effect in &mut self.effects {
+            effect.on_start_processing();
+        }
+    }
+
+    fn process(
+        &mut self,
+        dt: f64,
+        clock_info_provider: &ClockInfoProvider,
+        modulator_value_provider: &ModulatorValueProvider,
+    ) -> Frame {
+        let sample_rate = (1.0 / dt).round() as u32;
+        match self.sample_rate {
+            None => {
+                for effect in &mut self.effects {
+                    effect.init(sample_rate);
+                }
+                self.sample_rate = Some(sample_rate);
+            }
+            Some(current) if current != sample_rate => {
+                for effect in &mut self.effects {
+                    effect.on_change_sample_rate(sample_rate);
+                }
+                self.sample_rate = Some(sample_rate);
+            }
+            Some(_) => {}
+        }
+
+        let sound_dt = match &mut self.doppler {
+            Some(doppler) => dt * doppler.next(dt),
+            None => dt,
+        };
+        let input = self
+            .sound
+            .process(sound_dt, clock_info_provider, modulator_value_provider);
+        let input = match &mut self.occlusion {
+            Some(occlusion) => occlusion.process(input, dt),
+            None => input,
+        };
+        let input = match &mut self.cone {
+            Some(cone) => cone.process(input, dt),
+            None => input,
+        };
+        let input = match &mut self.air_absorption {
+            Some(air_absorption) => air_absorption.process(input, dt),
+            None => input,
+        };
+        #[cfg(feature = "hrtf")]
+        let input = match &mut self.binaural {
+            Some(binaural) => binaural.process(input, dt),
+            None => input,
+        };
+        let output = self.effects.iter_mut().fold(input, |frame, effect| {
+            effect.process(frame, dt, clock_info_provider, modulator_value_provider)
+        });
+        let output = match &mut self.voice {
+            Some(voice) => voice.process(output, dt),
+            None => output,
+        };
+        match &mut self.surround {
+            Some(surround) => surround.process(output, dt),
+            None => output,
+        }
+    }
+
+    fn finished(&self) -> bool {
+        let finished =
+            self.sound.finished() || self.voice.as_ref().is_some_and(VoiceProcessor::faded_out);
+        if finished {
+            if let Some(voice) = &self.voice {
+                voice.finish();
+            }
+        }
+        finished
+    }
+}
@@ -32,12 +32,14 @@
 //!
 //! fn main() {
 //!     App::new()
-//!         .insert_non_send_resource(AudioSettings {
-//!             // Only needed for tests
-//!             backend_settings: AudioBackendSelector::Mock { sample_rate: 48000, },
-//!             ..default()
-//!         })
-//!         .add_plugins((DefaultPlugins, AudioPlugin))
+//!         .add_plugins((
+//!             DefaultPlugins,
+//!             AudioPlugin {
+//!                 // Only needed for tests
+//!                 backend: AudioBackendSelector::Mock { sample_rate: 48000 },
+//!                 ..default()
+//!             },
+//!         ))
 //!         .add_systems(Startup, add_sound)
 //!         .run();
 //! }
@@ -51,39 +53,67 @@
 //! ```
 #![warn(missing_docs)]
 
+use std::fmt;
+use std::sync::Arc;
+
 use bevy::prelude::*;
 use bevy::transform::TransformSystem;
 pub use kira;
-use kira::manager::{AudioManager, AudioManagerSettings};
+use kira::manager::{AudioManager, AudioManagerSettings, Capacities};
+use kira::track::TrackBuilder;
+use kira::tween::Tween;
 
-use crate::backend::AudioBackend;
+use crate::analysis::AudioAnalysisPlugin;
+use crate::backend::surround::SurroundBus;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::backend::BackendStatistics;
+use crate::backend::{AudioBackend, AudioBackendSelector};
+use crate::clock::AudioClockPlugin;
+use crate::music::MusicPlugin;
 use crate::sources::audio_file::AudioFilePlugin;
+use crate::sources::synth::SynthPlugin;
+use crate::sources::SoundLimitBehavior;
 use crate::spatial::SpatialAudioPlugin;
+use crate::tracks::AudioTrackPlugin;
+use crate::voices::VoicePlugin;
 
+pub mod analysis;
 mod backend;
+pub mod clock;
 pub mod diagnostics;
+mod effects;
+pub mod music;
 pub mod sources;
 pub mod spatial;
+pub mod tracks;
+pub mod voices;
 
 #[doc(hidden)]
 #[allow(missing_docs)]
 pub mod prelude {
-    pub use super::{AudioPlaybackSet, AudioPlugin, AudioSettings, AudioWorld};
+    pub use super::{AudioPlaybackSet, AudioPlugin, AudioWorld};
+    pub use crate::analysis::prelude::*;
     pub use crate::backend::*;
+    pub use crate::clock::prelude::*;
+    pub use crate::music::prelude::*;
     pub use crate::sources::prelude::*;
     pub use crate::spatial::prelude::*;
+    pub use crate::tracks::prelude::*;
+    pub use crate::voices::prelude::*;
 }
 
-/// Type of settings for the audio engine. Insert it as a resource before adding the plugin to
-/// change the default settings.
-pub type AudioSettings = AudioManagerSettings<AudioBackend>;
-
 /// System set used in grouping systems that setup audio sources. Used in the
 /// [`AudioSourcePlugin`](prelude::AudioSourcePlugin)'s systems. Useful to place systems right
 /// after to be able to react to added audio assets.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, SystemSet)]
 pub struct AudioSourceSetup;
 
+/// System set used in grouping systems that request effects to be inserted into the signal path of
+/// audio sources and tracks (e.g. for [`AudioMeter`](prelude::AudioMeter)). These systems run
+/// right before sources and tracks are registered with the audio engine.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, SystemSet)]
+pub struct AudioEffectsSetup;
+
 /// General audio system set, used by the systems in this plugin.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, SystemSet)]
 pub enum AudioPlaybackSet {
@@ -99,22 +129,125 @@ pub enum AudioPlaybackSet {
 }
 
 /// Adds audio to Bevy games via the [`kira`] crate.
-#[derive(Debug, Default)]
-pub struct AudioPlugin;
+///
+/// The fields of the plugin configure the audio engine, which is created when the plugin is
+/// added to the app.
+///
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_kira_components::prelude::*;
+/// # use bevy_kira_components::kira::manager::Capacities;
+/// App::new().add_plugins((
+///     DefaultPlugins,
+///     AudioPlugin {
+///         capacities: Capacities {
+///             sound_capacity: 512,
+///             ..default()
+///         },
+///         ..default()
+///     },
+/// ));
+/// ```
+#[derive(Default)]
+pub struct AudioPlugin {
+    /// Audio backend outputting the audio.
+    pub backend: AudioBackendSelector,
+    /// Backend to use when the selected one cannot be created, for example when there is no
+    /// audio device available. When `None` (the default), failing to create the backend panics.
+    /// Set it to a [`AudioBackendSelector::Mock`] backend to leave the game running without
+    /// sound instead.
+    pub fallback_backend: Option<AudioBackendSelector>,
+    /// Maximum number of resources of each type in the audio engine. By default, there can be
+    /// 128 sounds, 128 tracks, 8 clocks, 8 spatial scenes and 16 modulators at once, and 128
+    /// commands sent to the audio engine per frame.
+    pub capacities: Capacities,
+    /// What to do with sources that cannot be played because the sound capacity is reached.
+    pub sound_limit: SoundLimitBehavior,
+    /// Creates the builder of the main mixer track, for example to add effects to it. See
+    /// [`Self::with_main_track`].
+    pub main_track: Option<Arc<dyn Fn() -> TrackBuilder + Send + Sync>>,
+}
+
+impl fmt::Debug for AudioPlugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AudioPlugin")
+            .field("backend", &self.backend)
+            .field("fallback_backend", &self.fallback_backend)
+            .field("capacities", &self.capacities)
+            .field("sound_limit", &self.sound_limit)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AudioPlugin {
+    /// Sets the function creating the builder of the main mixer track. It is called again if the
+    /// backend falls back to [`Self::fallback_backend`].
+    ///
+    /// The volume and effects of the builder are not applied to the surround channels (see
+    /// [`surround`](crate::spatial::surround)); use [`AudioWorld::set_main_volume`] to set the
+    /// volume instead.
+    pub fn with_main_track(
+        mut self,
+        main_track: impl Fn() -> TrackBuilder + Send + Sync + 'static,
+    ) -> Self {
+        self.main_track = Some(Arc::new(main_track));
+        self
+    }
+
+    fn manager_settings(
+        &self,
+        backend: AudioBackendSelector,
+    ) -> AudioManagerSettings<AudioBackend> {
+        AudioManagerSettings {
+            capacities: self.capacities,
+            main_track_builder: self
+                .main_track
+                .as_ref()
+                .map_or_else(TrackBuilder::default, |main_track| main_track()),
+            backend_settings: backend,
+        }
+    }
+
+    fn create_audio_manager(&self) -> AudioManager<AudioBackend> {
+        let err = match AudioManager::new(self.manager_settings(self.backend.clone())) {
+            Ok(manager) => return manager,
+            Err(err) => err,
+        };
+        let Some(fallback) = self.fallback_backend.clone() else {
+            panic!("Cannot create audio backend: {err}");
+        };
+        error!("Cannot create audio backend, falling back to {fallback:?}: {err}");
+        AudioManager::new(self.manager_settings(fallback))
+            .expect("Cannot create fallback audio backend")
+    }
+}
 
 impl Plugin for AudioPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<AudioWorld>()
+        app.insert_resource(AudioWorld::new(self.create_audio_manager()))
+            .insert_resource(self.sound_limit)
             .add_plugins((
                 #[cfg(feature = "diagnostics")]
                 diagnostics::KiraStatisticsDiagnosticPlugin,
                 SpatialAudioPlugin,
+                AudioTrackPlugin,
+                VoicePlugin,
+                AudioClockPlugin,
+                AudioAnalysisPlugin,
+                MusicPlugin,
                 AudioFilePlugin,
+                SynthPlugin,
             ))
             .configure_sets(PreUpdate, AudioPlaybackSet::Setup)
             .configure_sets(
                 PostUpdate,
                 AudioPlaybackSet::Update.after(TransformSystem::TransformPropagate),
+            )
+            .configure_sets(
+                PostUpdate,
+                AudioEffectsSetup
+                    .in_set(AudioPlaybackSet::Update)
+                    .before(AudioSourceSetup),
             );
     }
 }
@@ -123,16 +256,48 @@ impl Plugin for AudioPlugin {
 #[derive(Resource)]
 pub struct AudioWorld {
     pub(crate) audio_manager: AudioManager<AudioBackend>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) backend_statistics: Option<Arc<BackendStatistics>>,
+    /// Surround channels fed by spatial emitters, when outputting to a surround speaker layout
+    pub(crate) surround: Option<Arc<SurroundBus>>,
+    /// Volume of the main track, mirrored for the surround channels which are mixed outside of
+    /// Kira
+    main_volume: f64,
 }
 
-impl FromWorld for AudioWorld {
-    fn from_world(world: &mut World) -> Self {
-        let audio_manager_settings = world
-            .remove_non_send_resource::<AudioSettings>()
-            .unwrap_or_default();
-        let audio_manager =
-            AudioManager::new(audio_manager_settings).expect("Cannot create audio backend");
-        Self { audio_manager }
+impl AudioWorld {
+    fn new(mut audio_manager: AudioManager<AudioBackend>) -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            backend_statistics: audio_manager.backend_mut().statistics(),
+            surround: audio_manager.backend_mut().surround(),
+            audio_manager,
+            main_volume: 1.0,
+        }
+    }
+
+    /// Sets the volume of the main mixer track, as an amplitude.
+    ///
+    /// Set the volume of the main track this way rather than on its builder (see
+    /// [`AudioPlugin::with_main_track`]) for the surround channels to follow it, as they are
+    /// mixed outside of Kira. They follow the new volume right away, without the tween.
+    pub fn set_main_volume(&mut self, volume: f64, tween: Tween) {
+        self.audio_manager.main_track().set_volume(volume, tween);
+        self.main_volume = volume;
+    }
+
+    /// Volume of the main mixer track, as an amplitude, as set with [`Self::set_main_volume`].
+    /// Defaults to 1.
+    pub fn main_volume(&self) -> f64 {
+        self.main_volume
+    }
+
+    /// Statistics about the audio callback (DSP load, underruns and overruns), when the audio
+    /// backend supports measuring them. Enable the `diagnostics` feature to have them reported as
+    /// Bevy diagnostics.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn backend_statistics(&self) -> Option<&BackendStatistics> {
+        self.backend_statistics.as_deref()
     }
 }
 
@@ -0,0 +1,400 @@
+//! Layered music, playing several stems of a song in sync.
+//!
+//! All the stems (or layers) of a [`LayeredMusic`] are processed by a single sound in the audio
+//! engine, which keeps them sample-locked: they start on the same sample, and pausing, resuming
+//! and seeking is applied to all the layers at once. The volume of each layer can be changed
+//! independently, to fade layers in and out with the intensity of the game.
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+
+use bevy::asset::io::Reader;
+use bevy::asset::{ron, AssetLoader, AsyncReadExt, LoadContext, LoadDirectError};
+use bevy::asset::{Asset, ParseAssetPathError};
+use bevy::prelude::*;
+use kira::clock::clock_info::ClockInfoProvider;
+use kira::manager::error::PlaySoundError;
+use kira::modulator::value_provider::ModulatorValueProvider;
+use kira::sound::{PlaybackRate, PlaybackState, Region, Sound, SoundData};
+use kira::tween::{Tween, Value};
+use kira::{Frame, OutputDestination, StartTime, Volume};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::prelude::{
+    AudioBundle, AudioFile, AudioFileError, AudioFileHandle, AudioFileSettings, AudioSource,
+    SoundPlayer,
+};
+
+/// Number of commands that can be sent to the layered music between two audio callbacks.
+const COMMAND_CAPACITY: usize = 64;
+
+/// Specialization of [`AudioBundle`] for the [`LayeredMusic`] asset.
+pub type LayeredMusicBundle = AudioBundle<LayeredMusic>;
+
+/// Bevy [`Asset`] made of several audio files (stems) played in sync.
+///
+/// It can be loaded from `.layers.ron` files listing the paths of the stems, relative to the
+/// file:
+///
+/// ```ron
+/// (
+///     layers: ["combat_drums.ogg", "combat_bass.ogg", "combat_strings.ogg"],
+/// )
+/// ```
+#[derive(Asset, Clone, TypePath)]
+pub struct LayeredMusic {
+    /// Stems of the music, in the order of their layer index.
+    pub layers: Vec<AudioFile>,
+}
+
+impl LayeredMusic {
+    /// Creates layered music out of the given stems.
+    pub fn new(layers: impl IntoIterator<Item = AudioFile>) -> Self {
+        Self {
+            layers: layers.into_iter().collect(),
+        }
+    }
+}
+
+/// Settings available to the user when instantiating layered music.
+#[derive(Debug, Clone, Component)]
+pub struct LayeredMusicSettings {
+    /// By default, the music will start playing right away when inserted. Setting this to `true`
+    /// prevents that.
+    pub start_paused: bool,
+    /// Initial volume of each layer, by layer index. Layers without a volume set here play at
+    /// full volume.
+    pub layer_volumes: Vec<f64>,
+    /// Optionally loop a region of the layers (given in seconds).
+    pub loop_region: Option<Region>,
+    /// When the music should start playing.
+    pub start_time: StartTime,
+}
+
+impl Default for LayeredMusicSettings {
+    fn default() -> Self {
+        Self {
+            start_paused: false,
+            layer_volumes: vec![],
+            loop_region: None,
+            start_time: StartTime::Immediate,
+        }
+    }
+}
+
+impl AudioSource for LayeredMusic {
+    type Error = PlaySoundError<AudioFileError>;
+    type Handle = LayeredMusicHandle;
+    type Settings = LayeredMusicSettings;
+
+    fn create_handle(
+        &self,
+        manager: &mut SoundPlayer,
+        settings: &Self::Settings,
+        output_destination: OutputDestination,
+    ) -> Result<Self::Handle, Self::Error> {
+        let layers = self
+            .layers
+            .iter()
+            .enumerate()
+            .map(|(index, file)| {
+                file.create_sound(&AudioFileSettings {
+                    start_paused: settings.start_paused,
+                    volume: settings.layer_volumes.get(index).copied().unwrap_or(1.0),
+                    loop_region: settings.loop_region,
+                    start_time: settings.start_time,
+                    ..default()
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(PlaySoundError::IntoSoundError)?;
+        manager.play(LayeredSoundData {
+            layers,
+            output_destination,
+        })
+    }
+}
+
+/// Commands sent from the [`LayeredMusicHandle`] to the sound on the audio thread.
+#[derive(Copy, Clone)]
+enum LayerCommand {
+    SetLayerVolume(usize, Value<Volume>, Tween),
+    SetPlaybackRate(Value<PlaybackRate>, Tween),
+    Pause(Tween),
+    Resume(StartTime, Tween),
+    Stop(Tween),
+    SeekTo(f64),
+    SeekBy(f64),
+}
+
+/// State of the layers, shared with the [`LayeredMusicHandle`].
+#[derive(Default)]
+struct SharedState {
+    playback_state: AtomicU8,
+    /// Position of the layers in seconds, stored as the bits of a `f64`
+    position: AtomicU64,
+}
+
+fn playback_state_to_u8(state: PlaybackState) -> u8 {
+    match state {
+        PlaybackState::Playing => 0,
+        PlaybackState::Pausing => 1,
+        PlaybackState::Paused => 2,
+        PlaybackState::Stopping => 3,
+        PlaybackState::Stopped => 4,
+    }
+}
+
+fn playback_state_from_u8(state: u8) -> PlaybackState {
+    match state {
+        0 => PlaybackState::Playing,
+        1 => PlaybackState::Pausing,
+        2 => PlaybackState::Paused,
+        3 => PlaybackState::Stopping,
+        _ => PlaybackState::Stopped,
+    }
+}
+
+/// Handle to playing layered music. Access this component in your systems to control the music.
+///
+/// Commands are applied to all the layers at the start of the same audio callback, so that they
+/// stay in sync. Dropping the handle (e.g. by despawning its entity) stops the music.
+pub struct LayeredMusicHandle {
+    commands: HeapProducer<LayerCommand>,
+    shared: Arc<SharedState>,
+    layer_count: usize,
+}
+
+impl LayeredMusicHandle {
+    fn send(&mut self, command: LayerCommand) {
+        if self.commands.push(command).is_err() {
+            error!("Too many commands sent to layered music, command ignored");
+        }
+    }
+
+    /// Number of layers of the music.
+    pub fn layer_count(&self) -> usize {
+        self.layer_count
+    }
+
+    /// Current playback state of the layers.
+    pub fn playback_state(&self) -> PlaybackState {
+        playback_state_from_u8(self.shared.playback_state.load(Ordering::Relaxed))
+    }
+
+    /// Current playback position of the layers, in seconds.
+    pub fn position(&self) -> f64 {
+        f64::from_bits(self.shared.position.load(Ordering::Relaxed))
+    }
+
+    /// Sets the volume of a single layer. Does nothing if there is no layer at this index.
+    pub fn set_layer_volume(
+        &mut self,
+        layer: usize,
+        volume: impl Into<Value<Volume>>,
+        tween: Tween,
+    ) {
+        self.send(LayerCommand::SetLayerVolume(layer, volume.into(), tween));
+    }
+
+    /// Sets the playback rate of all the layers.
+    pub fn set_playback_rate(&mut self, rate: impl Into<Value<PlaybackRate>>, tween: Tween) {
+        self.send(LayerCommand::SetPlaybackRate(rate.into(), tween));
+    }
+
+    /// Fades out and pauses all the layers.
+    pub fn pause(&mut self, tween: Tween) {
+        self.send(LayerCommand::Pause(tween));
+    }
+
+    /// Resumes and fades in all the layers.
+    pub fn resume(&mut self, tween: Tween) {
+        self.resume_at(StartTime::Immediate, tween);
+    }
+
+    /// Resumes and fades in all the layers at the given start time.
+    pub fn resume_at(&mut self, start_time: StartTime, tween: Tween) {
+        self.send(LayerCommand::Resume(start_time, tween));
+    }
+
+    /// Fades out and stops all the layers. Stopped music cannot be resumed.
+    pub fn stop(&mut self, tween: Tween) {
+        self.send(LayerCommand::Stop(tween));
+    }
+
+    /// Moves the playback position of all the layers to the given position, in seconds.
+    pub fn seek_to(&mut self, position: f64) {
+        self.send(LayerCommand::SeekTo(position));
+    }
+
+    /// Moves the playback position of all the layers by the given amount of seconds.
+    pub fn seek_by(&mut self, amount: f64) {
+        self.send(LayerCommand::SeekBy(amount));
+    }
+}
+
+impl Drop for LayeredMusicHandle {
+    fn drop(&mut self) {
+        self.stop(Tween::default());
+    }
+}
+
+/// Sound data of layered music, made of the already created sounds of each layer.
+struct LayeredSoundData {
+    layers: Vec<(Box<dyn Sound>, AudioFileHandle)>,
+    output_destination: OutputDestination,
+}
+
+impl SoundData for LayeredSoundData {
+    type Error = AudioFileError;
+    type Handle = LayeredMusicHandle;
+
+    fn into_sound(self) -> Result<(Box<dyn Sound>, Self::Handle), Self::Error> {
+        let (producer, consumer) = HeapRb::new(COMMAND_CAPACITY).split();
+        let shared = Arc::new(SharedState::default());
+        let handle = LayeredMusicHandle {
+            commands: producer,
+            shared: shared.clone(),
+            layer_count: self.layers.len(),
+        };
+        let sound = LayeredSound {
+            layers: self.layers,
+            commands: consumer,
+            shared,
+            output_destination: self.output_destination,
+        };
+        Ok((Box::new(sound), handle))
+    }
+}
+
+/// Sound mixing the sounds of all the layers.
+///
+/// The handles of the layers are kept on the audio thread, so that commands are forwarded to all
+/// of them right before the layers read their commands, on the same audio callback.
+struct LayeredSound {
+    layers: Vec<(Box<dyn Sound>, AudioFileHandle)>,
+    commands: HeapConsumer<LayerCommand>,
+    shared: Arc<SharedState>,
+    output_destination: OutputDestination,
+}
+
+impl Sound for LayeredSound {
+    fn output_destination(&mut self) -> OutputDestination {
+        self.output_destination
+    }
+
+    fn on_start_processing(&mut self) {
+        for command in self.commands.pop_iter() {
+            if let LayerCommand::SetLayerVolume(layer, volume, tween) = command {
+                if let Some((_, handle)) = self.layers.get_mut(layer) {
+                    handle.set_volume(volume, tween);
+                }
+                continue;
+            }
+            for (_, handle) in &mut self.layers {
+                match command {
+                    LayerCommand::SetLayerVolume(..) => unreachable!(),
+                    LayerCommand::SetPlaybackRate(rate, tween) => {
+                        handle.set_playback_rate(rate, tween)
+                    }
+                    LayerCommand::Pause(tween) => handle.pause(tween),
+                    LayerCommand::Resume(start_time, tween) => handle.resume_at(start_time, tween),
+                    LayerCommand::Stop(tween) => handle.stop(tween),
+                    LayerCommand::SeekTo(position) => handle.seek_to(position),
+                    LayerCommand::SeekBy(amount) => handle.seek_by(amount),
+                }
+            }
+        }
+        for (sound, _) in &mut self.layers {
+            sound.on_start_processing();
+        }
+        let (state, position) = match self.layers.first() {
+            Some((_, handle)) => (handle.playback_state(), handle.position()),
+            None => (PlaybackState::Stopped, 0.0),
+        };
+        self.shared
+            .playback_state
+            .store(playback_state_to_u8(state), Ordering::Relaxed);
+        self.shared
+            .position
+            .store(position.to_bits(), Ordering::Relaxed);
+    }
+
+    fn process(
+        &mut self,
+        dt: f64,
+        clock_info_provider: &ClockInfoProvider,
+        modulator_value_provider: &ModulatorValueProvider,
+    ) -> Frame {
+        self.layers
+            .iter_mut()
+            .fold(Frame::ZERO, |frame, (sound, _)| {
+                frame + sound.process(dt, clock_info_provider, modulator_value_provider)
+            })
+    }
+
+    fn finished(&self) -> bool {
+        self.layers.iter().all(|(sound, _)| sound.finished())
+    }
+}
+
+/// Loads a [`LayeredMusic`] from a `.layers.ron` file.
+#[derive(Default)]
+pub struct LayeredMusicLoader;
+
+/// Possible errors that can be produced by [`LayeredMusicLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum LayeredMusicLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// The file is not a valid layered music description
+    #[error("Could not parse the file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    /// The path of a layer is invalid
+    #[error("Invalid layer path: {0}")]
+    Path(#[from] ParseAssetPathError),
+    /// A layer could not be loaded
+    #[error("Could not load a layer: {0}")]
+    Layer(#[from] LoadDirectError),
+}
+
+/// Contents of a `.layers.ron` file.
+#[derive(Deserialize, Serialize)]
+struct LayeredMusicDescription {
+    layers: Vec<String>,
+}
+
+impl AssetLoader for LayeredMusicLoader {
+    type Asset = LayeredMusic;
+    type Settings = ();
+    type Error = LayeredMusicLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).await?;
+        let description = ron::de::from_bytes::<LayeredMusicDescription>(&bytes)?;
+        let mut layers = Vec::with_capacity(description.layers.len());
+        for path in &description.layers {
+            let path = load_context.asset_path().resolve_embed(path)?;
+            let layer = load_context
+                .loader()
+                .direct()
+                .load::<AudioFile>(path)
+                .await?;
+            layers.push(layer.take());
+        }
+        Ok(LayeredMusic { layers })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["layers.ron"]
+    }
+}
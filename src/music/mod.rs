@@ -0,0 +1,59 @@
+//! Music playback, built on top of [`AudioFile`](crate::prelude::AudioFile) sources.
+//!
+//! Music is played through a [`MusicPlayer`](player::MusicPlayer) component, which takes care of
+//! spawning and despawning the entities playing each track, and of crossfading between them, or
+//! through a [`Playlist`](playlist::Playlist) component, playing tracks back to back. Songs split
+//! into stems can be played in sync with the [`LayeredMusic`] source, and
+//! music authored as segments can be re-sequenced with a
+//! [`MusicSequencer`](sequencer::MusicSequencer).
+use bevy::prelude::*;
+
+use crate::music::layered::{LayeredMusic, LayeredMusicLoader};
+use crate::music::sequencer::{MusicSegmentStarted, MusicStateChange, MusicStinger};
+use crate::prelude::AudioSourcePlugin;
+use crate::{AudioEffectsSetup, AudioPlaybackSet};
+
+pub mod layered;
+pub mod player;
+pub mod playlist;
+pub mod sequencer;
+
+#[doc(hidden)]
+#[allow(missing_docs)]
+pub mod prelude {
+    pub use super::layered::{
+        LayeredMusic, LayeredMusicBundle, LayeredMusicHandle, LayeredMusicLoader,
+        LayeredMusicLoaderError, LayeredMusicSettings,
+    };
+    pub use super::player::{BeatSync, CrossfadeSettings, MusicPlayer, MusicTrack};
+    pub use super::playlist::{Playlist, RepeatMode};
+    pub use super::sequencer::{
+        MusicSegment, MusicSegmentStarted, MusicSequencer, MusicStateChange, MusicStinger,
+        Quantization,
+    };
+}
+
+/// Music plugin. This is an internal plugin, useful for some separation of concerns.
+///
+/// It is automatically added by the main [`AudioPlugin`](crate::AudioPlugin).
+pub(crate) struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset_loader::<LayeredMusicLoader>()
+            .add_plugins(AudioSourcePlugin::<LayeredMusic>::default())
+            .add_event::<MusicStateChange>()
+            .add_event::<MusicStinger>()
+            .add_event::<MusicSegmentStarted>()
+            .add_systems(
+                PostUpdate,
+                (
+                    player::update_music_players,
+                    playlist::update_playlists,
+                    sequencer::update_sequencers,
+                )
+                    .in_set(AudioPlaybackSet::Update)
+                    .before(AudioEffectsSetup),
+            );
+    }
+}
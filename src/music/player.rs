@@ -0,0 +1,257 @@
+//! Music player, crossfading between music tracks.
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use kira::sound::PlaybackState;
+use kira::tween::{Easing, Tween};
+use kira::StartTime;
+
+use crate::clock::AudioClockHandle;
+use crate::prelude::{
+    AudioFile, AudioFileBundle, AudioFileEndBehavior, AudioFileHandle, AudioFileSettings,
+    AudioHandle, OutputDestination,
+};
+
+/// Aligns the start of a transition on the ticks of a clock.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BeatSync {
+    /// Entity with the [`AudioClock`](crate::prelude::AudioClock) to synchronize to.
+    pub clock: Entity,
+    /// Number of ticks between possible start times. For example, with a clock ticking on every
+    /// beat of a song in 4/4, an interval of 4 starts transitions on the next bar.
+    pub interval: u64,
+}
+
+/// Settings of the transition between two music tracks.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CrossfadeSettings {
+    /// Duration of the crossfade. The outgoing track fades out while the incoming track fades in
+    /// over this duration. A zero duration switches tracks right away.
+    pub duration: Duration,
+    /// Easing curve of the fades.
+    pub curve: Easing,
+    /// Optionally wait for the next beat of a clock to start the transition.
+    pub sync: Option<BeatSync>,
+}
+
+impl Default for CrossfadeSettings {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(2),
+            curve: Easing::Linear,
+            sync: None,
+        }
+    }
+}
+
+impl CrossfadeSettings {
+    fn tween(&self, start_time: StartTime) -> Tween {
+        Tween {
+            start_time,
+            duration: self.duration,
+            easing: self.curve,
+        }
+    }
+}
+
+/// Music track to be played by a [`MusicPlayer`].
+#[derive(Debug, Default)]
+pub struct MusicTrack {
+    /// Handle to the audio file of the track.
+    pub source: Handle<AudioFile>,
+    /// Settings of the audio file. Note that [`AudioFileSettings::start_paused`] is ignored, as
+    /// the player starts tracks itself.
+    pub settings: AudioFileSettings,
+}
+
+impl From<Handle<AudioFile>> for MusicTrack {
+    fn from(source: Handle<AudioFile>) -> Self {
+        Self {
+            source,
+            settings: AudioFileSettings::default(),
+        }
+    }
+}
+
+enum MusicCommand {
+    Play(MusicTrack, CrossfadeSettings),
+    Stop(CrossfadeSettings),
+}
+
+/// Component playing music tracks one at a time, crossfading between them.
+///
+/// Each track is played by a child entity of the player, spawned with an [`AudioFileBundle`] and
+/// despawned once the track has finished playing or has been faded out. Tracks are sent to the
+/// [`OutputDestination`] of the player entity, if any.
+///
+/// Tracks start playing once their audio file is loaded. Despawn the player recursively to stop
+/// the music right away.
+///
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_kira_components::prelude::*;
+/// fn change_music(mut q: Query<&mut MusicPlayer>, asset_server: Res<AssetServer>) {
+///     let mut player = q.single_mut();
+///     player.play(asset_server.load("battle.ogg"), CrossfadeSettings::default());
+///     player.queue(asset_server.load("victory.ogg"), CrossfadeSettings::default());
+/// }
+/// ```
+#[derive(Component, Default)]
+pub struct MusicPlayer {
+    commands: Vec<MusicCommand>,
+    queue: VecDeque<(MusicTrack, CrossfadeSettings)>,
+    current: Option<Entity>,
+    incoming: Option<(Entity, CrossfadeSettings)>,
+}
+
+impl MusicPlayer {
+    /// Plays the given track, crossfading from the currently playing one. Queued tracks are kept,
+    /// and will play once this one finishes.
+    pub fn play(&mut self, track: impl Into<MusicTrack>, crossfade: CrossfadeSettings) {
+        self.commands
+            .push(MusicCommand::Play(track.into(), crossfade));
+    }
+
+    /// Queues the given track, to be played once the current track (and the tracks queued before
+    /// it) have finished playing. The track fades in using the given crossfade settings.
+    ///
+    /// Note that looping tracks never finish playing; use [`Self::play`] to switch from them.
+    pub fn queue(&mut self, track: impl Into<MusicTrack>, crossfade: CrossfadeSettings) {
+        self.queue.push_back((track.into(), crossfade));
+    }
+
+    /// Fades out the current track, and clears the queue.
+    pub fn stop(&mut self, crossfade: CrossfadeSettings) {
+        self.commands.push(MusicCommand::Stop(crossfade));
+    }
+
+    /// Removes all queued tracks.
+    pub fn clear_queue(&mut self) {
+        self.queue.clear();
+    }
+
+    /// Number of tracks waiting in the queue.
+    pub fn queue_len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Entity playing the current track. Query its
+    /// [`AudioHandle<AudioFileHandle>`](AudioHandle) component to control the track directly.
+    pub fn current(&self) -> Option<Entity> {
+        self.current
+    }
+}
+
+fn spawn_track(
+    commands: &mut Commands,
+    player: Entity,
+    track: MusicTrack,
+    output: OutputDestination,
+) -> Entity {
+    let MusicTrack {
+        source,
+        mut settings,
+    } = track;
+    // The track gets faded in by the player once its handle is available
+    settings.start_paused = true;
+    commands
+        .spawn((
+            AudioFileBundle {
+                source,
+                settings,
+                output,
+                ..default()
+            },
+            // Recursive despawning also removes the track from the children of the player
+            AudioFileEndBehavior::Despawn { recursive: true },
+        ))
+        .set_parent(player)
+        .id()
+}
+
+/// Returns the start time of a transition, or `None` if the clock to synchronize to does not
+/// exist yet.
+fn start_time(
+    crossfade: &CrossfadeSettings,
+    q_clocks: &Query<&AudioClockHandle>,
+) -> Option<StartTime> {
+    let Some(sync) = crossfade.sync else {
+        return Some(StartTime::Immediate);
+    };
+    let clock = q_clocks.get(sync.clock).ok()?;
+    Some(StartTime::ClockTime(clock.next_multiple(sync.interval)))
+}
+
+pub(super) fn update_music_players(
+    mut commands: Commands,
+    mut q_players: Query<(Entity, &mut MusicPlayer, Option<&OutputDestination>)>,
+    mut q_handles: Query<&mut AudioHandle<AudioFileHandle>>,
+    q_clocks: Query<&AudioClockHandle>,
+) {
+    for (entity, mut player, output) in &mut q_players {
+        let player = &mut *player;
+        let output = output.copied().unwrap_or_default();
+
+        for command in std::mem::take(&mut player.commands) {
+            if let Some((incoming, _)) = player.incoming.take() {
+                commands.entity(incoming).despawn_recursive();
+            }
+            match command {
+                MusicCommand::Play(track, crossfade) => {
+                    let incoming = spawn_track(&mut commands, entity, track, output);
+                    player.incoming = Some((incoming, crossfade));
+                }
+                MusicCommand::Stop(crossfade) => {
+                    player.queue.clear();
+                    let Some(current) = player.current.take() else {
+                        continue;
+                    };
+                    if let Ok(mut handle) = q_handles.get_mut(current) {
+                        let start_time = start_time(&crossfade, &q_clocks).unwrap_or_else(|| {
+                            warn!("Clock to synchronize to does not exist, stopping right away");
+                            StartTime::Immediate
+                        });
+                        handle.stop(crossfade.tween(start_time));
+                    }
+                }
+            }
+        }
+
+        // Move on to the next queued track once the current one has finished
+        let finished = player.current.map_or(true, |current| {
+            q_handles.get(current).map_or(true, |handle| {
+                matches!(handle.playback_state(), PlaybackState::Stopped)
+            })
+        });
+        if finished {
+            player.current = None;
+            if player.incoming.is_none() {
+                if let Some((track, crossfade)) = player.queue.pop_front() {
+                    let incoming = spawn_track(&mut commands, entity, track, output);
+                    player.incoming = Some((incoming, crossfade));
+                }
+            }
+        }
+
+        let Some((incoming, crossfade)) = player.incoming else {
+            continue;
+        };
+        let Ok(mut handle) = q_handles.get_mut(incoming) else {
+            // Track not loaded yet
+            continue;
+        };
+        let Some(start_time) = start_time(&crossfade, &q_clocks) else {
+            debug!("Clock not ready");
+            continue;
+        };
+        let tween = crossfade.tween(start_time);
+        handle.resume_at(start_time, tween);
+        player.incoming = None;
+        if let Some(current) = player.current.replace(incoming) {
+            if let Ok(mut handle) = q_handles.get_mut(current) {
+                handle.stop(tween);
+            }
+        }
+    }
+}
@@ -0,0 +1,306 @@
+//! Playlists, playing audio files back to back without gaps.
+use bevy::prelude::*;
+use kira::clock::{ClockHandle, ClockSpeed, ClockTime};
+use kira::sound::PlaybackState;
+use kira::tween::Tween;
+
+use crate::prelude::{
+    AudioFile, AudioFileBundle, AudioFileEndBehavior, AudioFileHandle, AudioFileSettings,
+    AudioHandle, OutputDestination,
+};
+use crate::AudioWorld;
+
+/// What to do once the playlist reaches the end of a track.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum RepeatMode {
+    /// Play each track once, and stop after the last one. This is the default.
+    #[default]
+    None,
+    /// Repeat the current track indefinitely.
+    One,
+    /// Go back to the first track after the last one. When shuffling, the order is shuffled again
+    /// on each repetition.
+    All,
+}
+
+/// Component playing a list of audio files back to back.
+///
+/// Transitions between tracks are gapless: the next track is created ahead of time, and scheduled
+/// to start on the exact sample the current one ends. Each track is played by a child entity of
+/// the playlist, spawned with an [`AudioFileBundle`] and despawned once the track has finished
+/// playing. Tracks are sent to the [`OutputDestination`] of the playlist entity, if any.
+///
+/// The next track is scheduled as soon as the current one starts playing, so changes to the
+/// playlist only apply from the track after it. The current track can be paused and seeked
+/// through its handle (see [`Self::current`]), the next track following it; change its playback
+/// rate with [`Self::playback_rate`] instead, for the next track to follow it as well. Despawn
+/// the playlist recursively to stop it.
+#[derive(Component)]
+pub struct Playlist {
+    /// Audio files to play.
+    pub tracks: Vec<Handle<AudioFile>>,
+    /// What to do when reaching the end of a track.
+    pub repeat: RepeatMode,
+    /// Play the tracks in a random order.
+    pub shuffle: bool,
+    /// Volume of the tracks. Defaults to 1.
+    pub volume: f64,
+    /// Playback rate of the tracks. Defaults to 1. Changes apply to the current track right away.
+    pub playback_rate: f64,
+    state: PlaylistState,
+}
+
+impl Default for Playlist {
+    fn default() -> Self {
+        Self::new([])
+    }
+}
+
+impl Playlist {
+    /// Creates a playlist of the given tracks, played once in order.
+    pub fn new(tracks: impl IntoIterator<Item = Handle<AudioFile>>) -> Self {
+        Self {
+            tracks: tracks.into_iter().collect(),
+            repeat: RepeatMode::None,
+            shuffle: false,
+            volume: 1.0,
+            playback_rate: 1.0,
+            state: PlaylistState::default(),
+        }
+    }
+
+    /// Index (in [`Self::tracks`]) of the track currently playing.
+    pub fn current_index(&self) -> Option<usize> {
+        self.state.current.as_ref().map(|track| track.index)
+    }
+
+    /// Entity playing the current track. Query its
+    /// [`AudioHandle<AudioFileHandle>`](AudioHandle) component to control the track directly.
+    pub fn current(&self) -> Option<Entity> {
+        self.state.current.as_ref().map(|track| track.entity)
+    }
+
+    /// Returns `true` once the playlist has played all its tracks. Only happens when
+    /// [`Self::repeat`] is [`RepeatMode::None`].
+    pub fn is_finished(&self) -> bool {
+        self.state.finished
+    }
+}
+
+/// Track created by the playlist.
+struct ScheduledTrack {
+    entity: Entity,
+    index: usize,
+    /// Time at which the track starts, in ticks of the playlist clock (i.e. seconds)
+    start: f64,
+}
+
+/// Internal playback state of a playlist.
+#[derive(Default)]
+struct PlaylistState {
+    /// Order in which the tracks are played
+    order: Vec<usize>,
+    /// Position in `order` of the last scheduled track
+    position: Option<usize>,
+    /// Clock the tracks are scheduled on, ticking once per second, and paused along with the
+    /// current track. A new clock is created each time playback (re)starts, so that the first
+    /// track starts at tick 0.
+    clock: Option<ClockHandle>,
+    /// Playback rate the tracks have been created with
+    playback_rate: f64,
+    current: Option<ScheduledTrack>,
+    next: Option<ScheduledTrack>,
+    finished: bool,
+}
+
+impl PlaylistState {
+    /// Picks the index of the next track to play, or `None` at the end of the playlist.
+    fn advance(&mut self, len: usize, repeat: RepeatMode, shuffle: bool) -> Option<usize> {
+        if len == 0 {
+            return None;
+        }
+        if self.order.len() != len {
+            self.reorder(len, shuffle);
+        }
+        let position = match (self.position, repeat) {
+            (None, _) => 0,
+            // The tracks may have been removed from under the current position
+            (Some(position), RepeatMode::One) if position < self.order.len() => position,
+            (Some(_), RepeatMode::One) => 0,
+            (Some(position), _) if position + 1 < len => position + 1,
+            (Some(_), RepeatMode::All) => {
+                self.reorder(len, shuffle);
+                0
+            }
+            (Some(_), RepeatMode::None) => return None,
+        };
+        self.position = Some(position);
+        Some(self.order[position])
+    }
+
+    fn reorder(&mut self, len: usize, shuffle: bool) {
+        self.order = (0..len).collect();
+        if shuffle {
+            fastrand::shuffle(&mut self.order);
+        }
+    }
+}
+
+fn spawn_track(
+    commands: &mut Commands,
+    playlist: Entity,
+    source: Handle<AudioFile>,
+    settings: AudioFileSettings,
+    output: OutputDestination,
+) -> Entity {
+    commands
+        .spawn((
+            AudioFileBundle {
+                source,
+                settings,
+                output,
+                ..default()
+            },
+            // Recursive despawning also removes the track from the children of the playlist
+            AudioFileEndBehavior::Despawn { recursive: true },
+        ))
+        .set_parent(playlist)
+        .id()
+}
+
+/// Difference between the end of the current track and the start of the next one over which
+/// the next track is scheduled again, in seconds.
+const RESCHEDULE_THRESHOLD: f64 = 0.001;
+
+pub(super) fn update_playlists(
+    mut commands: Commands,
+    mut audio_world: ResMut<AudioWorld>,
+    mut q_playlists: Query<(Entity, &mut Playlist, Option<&OutputDestination>)>,
+    mut q_handles: Query<Option<&mut AudioHandle<AudioFileHandle>>>,
+) {
+    for (entity, mut playlist, output) in &mut q_playlists {
+        let playlist = &mut *playlist;
+        let state = &mut playlist.state;
+        let output = output.copied().unwrap_or_default();
+        let playback_rate = playlist.playback_rate;
+        let settings = |start: ClockTime| AudioFileSettings {
+            volume: playlist.volume,
+            playback_rate,
+            start_time: start.into(),
+            ..default()
+        };
+
+        // Move on to the next track once the current one has finished
+        if let Some(current) = &state.current {
+            let finished = match q_handles.get(current.entity) {
+                Ok(handle) => handle.is_some_and(|handle| {
+                    matches!(handle.playback_state(), PlaybackState::Stopped)
+                }),
+                Err(_) => true,
+            };
+            if finished {
+                state.current = state.next.take();
+                if state.current.is_none() {
+                    // Nothing left to play, the chain of scheduled tracks is broken
+                    state.clock = None;
+                    state.finished = playlist.repeat == RepeatMode::None;
+                }
+            }
+        }
+
+        if state.current.is_none() && !state.finished && !playlist.tracks.is_empty() {
+            let manager = &mut audio_world.audio_manager;
+            if manager.num_clocks() >= manager.clock_capacity() {
+                warn_once!("Clock limit reached, playlists will start once clocks are removed");
+                continue;
+            }
+            // The clock only starts once the first track has been created, which will make it
+            // start right away
+            let clock = match manager.add_clock(ClockSpeed::TicksPerSecond(1.0)) {
+                Ok(clock) => clock,
+                Err(err) => {
+                    error!("Cannot create clock for playlist {entity:?}: {err}");
+                    continue;
+                }
+            };
+            let Some(index) =
+                state.advance(playlist.tracks.len(), playlist.repeat, playlist.shuffle)
+            else {
+                state.finished = true;
+                continue;
+            };
+            let start = ClockTime::from_ticks_u64(clock.id(), 0);
+            let source = playlist.tracks[index].clone();
+            state.current = Some(ScheduledTrack {
+                entity: spawn_track(&mut commands, entity, source, settings(start), output),
+                index,
+                start: 0.0,
+            });
+            state.clock = Some(clock);
+            state.playback_rate = playback_rate;
+        }
+
+        let (Some(current), Some(clock)) = (&state.current, &mut state.clock) else {
+            continue;
+        };
+        let Ok(Some(mut handle)) = q_handles.get_mut(current.entity) else {
+            // Track not created yet
+            continue;
+        };
+        if state.playback_rate != playback_rate {
+            handle.set_playback_rate(playback_rate, Tween::default());
+            state.playback_rate = playback_rate;
+        }
+        // Pausing the clock along with the current track keeps the next one waiting for it
+        let paused = matches!(
+            handle.playback_state(),
+            PlaybackState::Pausing | PlaybackState::Paused
+        );
+        if paused {
+            if clock.ticking() {
+                clock.pause();
+            }
+            continue;
+        }
+        if !clock.ticking() {
+            clock.start();
+        }
+
+        // Schedule the next track where the current one would end from its current position
+        let now = clock.time();
+        let position = handle.position();
+        if clock.time() != now {
+            // Audio has been rendered in between, the position is from a later time
+            continue;
+        }
+        let clock = now.clock;
+        let now = now.ticks as f64 + now.fraction;
+        let remaining = (handle.duration().as_secs_f64() - position).max(0.0);
+        let start = now + remaining / playback_rate.max(f64::EPSILON);
+        let index = match &state.next {
+            // Leave the next track alone once it has started
+            Some(next) if next.start <= now => continue,
+            Some(next) if (next.start - start).abs() <= RESCHEDULE_THRESHOLD => continue,
+            // The current track has been seeked, or its playback rate changed
+            Some(next) => {
+                commands.entity(next.entity).despawn_recursive();
+                next.index
+            }
+            None => {
+                let Some(index) =
+                    state.advance(playlist.tracks.len(), playlist.repeat, playlist.shuffle)
+                else {
+                    continue;
+                };
+                index
+            }
+        };
+        let clock_time = ClockTime::from_ticks_f64(clock, start);
+        let source = playlist.tracks[index].clone();
+        state.next = Some(ScheduledTrack {
+            entity: spawn_track(&mut commands, entity, source, settings(clock_time), output),
+            index,
+            start,
+        });
+    }
+}
@@ -0,0 +1,456 @@
+//! Horizontal re-sequencing of music, switching between segments on musically meaningful
+//! boundaries.
+//!
+//! Music is authored as [`MusicSegment`]s, each with its own tempo and time signature. A
+//! [`MusicSequencer`] plays one segment at a time, looping it or moving on to the segment set to
+//! follow it. Gameplay sends [`MusicStateChange`] events to switch segments, with the transition
+//! quantized to the next beat, bar or end of the current segment, and [`MusicStinger`] events to
+//! play short sounds in time with the music.
+//!
+//! All scheduling happens on a clock ticking once per beat, so that segments and stingers start
+//! on the exact sample of their beat.
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use kira::clock::{ClockHandle, ClockSpeed, ClockTime};
+use kira::tween::Tween;
+use kira::StartTime;
+
+use crate::prelude::{
+    AudioFile, AudioFileBundle, AudioFileEndBehavior, AudioFileHandle, AudioFileSettings,
+    AudioHandle, OutputDestination,
+};
+use crate::AudioWorld;
+
+/// Segment of music, looped or followed by another segment once it ends.
+#[derive(Debug, Clone)]
+pub struct MusicSegment {
+    /// Audio file of the segment. It can be longer than the segment itself (e.g. to include the
+    /// reverb tail), in which case it keeps playing over the next segment.
+    pub source: Handle<AudioFile>,
+    /// Tempo of the segment, in beats per minute.
+    pub tempo: f64,
+    /// Number of beats in a bar.
+    pub beats_per_bar: u32,
+    /// Length of the segment, in bars.
+    pub bars: u32,
+    /// Name of the segment to play once this one ends. When `None`, the segment loops.
+    pub next: Option<String>,
+}
+
+impl MusicSegment {
+    /// Creates a looping segment.
+    pub fn new(source: Handle<AudioFile>, tempo: f64, beats_per_bar: u32, bars: u32) -> Self {
+        Self {
+            source,
+            tempo,
+            beats_per_bar,
+            bars,
+            next: None,
+        }
+    }
+
+    /// Sets the segment to play once this one ends.
+    pub fn with_next(mut self, next: impl Into<String>) -> Self {
+        self.next = Some(next.into());
+        self
+    }
+
+    /// Length of the segment, in beats.
+    fn length(&self) -> u64 {
+        self.bars.max(1) as u64 * self.beats_per_bar.max(1) as u64
+    }
+}
+
+/// Musical boundary transitions and stingers are aligned to.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Quantization {
+    /// Next beat of the current segment.
+    Beat,
+    /// Next bar of the current segment. This is the default.
+    #[default]
+    Bar,
+    /// End of the current segment (or of its current loop).
+    SegmentEnd,
+}
+
+/// Event requesting a [`MusicSequencer`] to switch to another segment.
+///
+/// When nothing is playing, the segment starts right away. When a transition is already
+/// scheduled, the request is applied once that transition has happened.
+#[derive(Debug, Clone, Event)]
+pub struct MusicStateChange {
+    /// Entity with the [`MusicSequencer`].
+    pub sequencer: Entity,
+    /// Name of the segment to switch to.
+    pub segment: String,
+    /// Boundary the transition happens on.
+    pub quantization: Quantization,
+    /// Optional sound played on the first beat of the new segment.
+    pub stinger: Option<Handle<AudioFile>>,
+}
+
+/// Event requesting a [`MusicSequencer`] to play a stinger, a short sound played on top of the
+/// music, in time with it.
+#[derive(Debug, Clone, Event)]
+pub struct MusicStinger {
+    /// Entity with the [`MusicSequencer`].
+    pub sequencer: Entity,
+    /// Sound of the stinger.
+    pub sound: Handle<AudioFile>,
+    /// Boundary the stinger starts on.
+    pub quantization: Quantization,
+}
+
+/// Event sent when a [`MusicSequencer`] starts playing a segment, be it after a transition, or
+/// when the previous segment ended.
+#[derive(Debug, Clone, Event)]
+pub struct MusicSegmentStarted {
+    /// Entity with the [`MusicSequencer`].
+    pub sequencer: Entity,
+    /// Name of the segment.
+    pub segment: String,
+}
+
+/// Component playing music segments, switching between them on [`MusicStateChange`] events.
+///
+/// Each segment is played by a child entity of the sequencer, spawned with an
+/// [`AudioFileBundle`] and despawned once it has finished playing. Segments and stingers are sent
+/// to the [`OutputDestination`] of the sequencer entity, if any. Despawn the sequencer recursively
+/// to stop the music.
+#[derive(Component, Default)]
+pub struct MusicSequencer {
+    /// Segments available to the sequencer, by name.
+    pub segments: HashMap<String, MusicSegment>,
+    state: SequencerState,
+}
+
+impl MusicSequencer {
+    /// Creates a sequencer with the given segments. It stays silent until a
+    /// [`MusicStateChange`] event is sent to it.
+    pub fn new(segments: impl IntoIterator<Item = (impl Into<String>, MusicSegment)>) -> Self {
+        Self {
+            segments: segments
+                .into_iter()
+                .map(|(name, segment)| (name.into(), segment))
+                .collect(),
+            state: SequencerState::default(),
+        }
+    }
+
+    /// Name of the segment currently playing.
+    pub fn current_segment(&self) -> Option<&str> {
+        self.state
+            .current
+            .as_ref()
+            .map(|segment| segment.name.as_str())
+    }
+}
+
+/// Segment created by the sequencer.
+struct ScheduledSegment {
+    name: String,
+    entity: Entity,
+    /// Beat of the clock the segment starts on
+    start: u64,
+}
+
+/// Transition waiting to be scheduled.
+struct PendingTransition {
+    segment: String,
+    quantization: Quantization,
+    stinger: Option<Handle<AudioFile>>,
+}
+
+/// Internal playback state of a sequencer.
+#[derive(Default)]
+struct SequencerState {
+    /// Clock ticking on every beat, created when the first segment starts
+    clock: Option<ClockHandle>,
+    current: Option<ScheduledSegment>,
+    /// Segment scheduled to start after the current one
+    next: Option<ScheduledSegment>,
+    /// Whether `next` comes from a transition, in which case the current segment has been
+    /// scheduled to stop and the transition cannot be cancelled anymore
+    transition: bool,
+    pending: Option<PendingTransition>,
+    stingers: Vec<(Handle<AudioFile>, Quantization)>,
+}
+
+impl SequencerState {
+    /// Returns the first beat after the current time matching the quantization, or `None` when
+    /// nothing is playing.
+    fn quantize(
+        &self,
+        quantization: Quantization,
+        segments: &HashMap<String, MusicSegment>,
+    ) -> Option<u64> {
+        let (Some(clock), Some(current)) = (&self.clock, &self.current) else {
+            return None;
+        };
+        let next_beat = (clock.time().ticks + 1).max(current.start);
+        // Fall back to the next beat if the current segment has been removed
+        let Some(segment) = segments.get(&current.name) else {
+            return Some(next_beat);
+        };
+        let elapsed = next_beat - current.start;
+        let interval = match quantization {
+            Quantization::Beat => return Some(next_beat),
+            Quantization::Bar => segment.beats_per_bar.max(1) as u64,
+            Quantization::SegmentEnd => segment.length(),
+        };
+        Some(current.start + elapsed.div_ceil(interval) * interval)
+    }
+
+    /// Takes the pending transition along with its segment, dropping it if the segment has been
+    /// removed since the transition was requested.
+    fn take_pending<'a>(
+        &mut self,
+        segments: &'a HashMap<String, MusicSegment>,
+    ) -> Option<(PendingTransition, &'a MusicSegment)> {
+        let pending = self.pending.take()?;
+        let Some(segment) = segments.get(&pending.segment) else {
+            warn!(
+                "Music segment {:?} was removed, dropping the transition to it",
+                pending.segment
+            );
+            return None;
+        };
+        Some((pending, segment))
+    }
+}
+
+fn spawn_sound(
+    commands: &mut Commands,
+    sequencer: Entity,
+    source: Handle<AudioFile>,
+    start_time: StartTime,
+    output: OutputDestination,
+) -> Entity {
+    commands
+        .spawn((
+            AudioFileBundle {
+                source,
+                settings: AudioFileSettings {
+                    start_time,
+                    ..default()
+                },
+                output,
+                ..default()
+            },
+            // Recursive despawning also removes the sound from the children of the sequencer
+            AudioFileEndBehavior::Despawn { recursive: true },
+        ))
+        .set_parent(sequencer)
+        .id()
+}
+
+/// Tween changing a value exactly at the given start time.
+fn instant_tween(start_time: StartTime) -> Tween {
+    Tween {
+        start_time,
+        duration: Duration::ZERO,
+        ..default()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) fn update_sequencers(
+    mut commands: Commands,
+    mut audio_world: ResMut<AudioWorld>,
+    mut state_changes: EventReader<MusicStateChange>,
+    mut stingers: EventReader<MusicStinger>,
+    mut segment_started: EventWriter<MusicSegmentStarted>,
+    mut q_sequencers: Query<(Entity, &mut MusicSequencer, Option<&OutputDestination>)>,
+    mut q_handles: Query<Option<&mut AudioHandle<AudioFileHandle>>>,
+) {
+    for change in state_changes.read() {
+        let Ok((_, mut sequencer, _)) = q_sequencers.get_mut(change.sequencer) else {
+            warn!(
+                "Music state change sent to {:?}, which is not a sequencer",
+                change.sequencer
+            );
+            continue;
+        };
+        if !sequencer.segments.contains_key(&change.segment) {
+            warn!("Unknown music segment {:?}", change.segment);
+            continue;
+        }
+        sequencer.state.pending = Some(PendingTransition {
+            segment: change.segment.clone(),
+            quantization: change.quantization,
+            stinger: change.stinger.clone(),
+        });
+    }
+    for stinger in stingers.read() {
+        let Ok((_, mut sequencer, _)) = q_sequencers.get_mut(stinger.sequencer) else {
+            warn!(
+                "Music stinger sent to {:?}, which is not a sequencer",
+                stinger.sequencer
+            );
+            continue;
+        };
+        sequencer
+            .state
+            .stingers
+            .push((stinger.sound.clone(), stinger.quantization));
+    }
+
+    for (entity, mut sequencer, output) in &mut q_sequencers {
+        let sequencer = &mut *sequencer;
+        let segments = &sequencer.segments;
+        let state = &mut sequencer.state;
+        let output = output.copied().unwrap_or_default();
+
+        // Start playing from silence
+        if state.current.is_none() {
+            if let Some((pending, segment)) = state.take_pending(segments) {
+                let manager = &mut audio_world.audio_manager;
+                if manager.num_clocks() >= manager.clock_capacity() {
+                    warn_once!(
+                        "Clock limit reached, sequencers will start once clocks are removed"
+                    );
+                    state.pending = Some(pending);
+                    continue;
+                }
+                // The clock only starts once the segment has been created, which will make it
+                // start right away
+                let clock = match manager.add_clock(ClockSpeed::TicksPerMinute(segment.tempo)) {
+                    Ok(clock) => clock,
+                    Err(err) => {
+                        error!("Cannot create clock for sequencer {entity:?}: {err}");
+                        continue;
+                    }
+                };
+                let start_time = StartTime::ClockTime(ClockTime::from_ticks_u64(clock.id(), 0));
+                let source = segment.source.clone();
+                state.current = Some(ScheduledSegment {
+                    name: pending.segment.clone(),
+                    entity: spawn_sound(&mut commands, entity, source, start_time, output),
+                    start: 0,
+                });
+                if let Some(stinger) = pending.stinger {
+                    spawn_sound(&mut commands, entity, stinger, start_time, output);
+                }
+                state.clock = Some(clock);
+                segment_started.send(MusicSegmentStarted {
+                    sequencer: entity,
+                    segment: pending.segment,
+                });
+            }
+        }
+
+        let (Some(clock), Some(current)) = (&mut state.clock, &state.current) else {
+            // Stingers are not quantized when no music is playing
+            for (sound, _) in state.stingers.drain(..) {
+                spawn_sound(&mut commands, entity, sound, StartTime::Immediate, output);
+            }
+            continue;
+        };
+        if !clock.ticking() {
+            if let Ok(Some(_)) = q_handles.get(current.entity) {
+                clock.start();
+            }
+            continue;
+        }
+        let clock_id = clock.id();
+        let at = |beat: u64| StartTime::ClockTime(ClockTime::from_ticks_u64(clock_id, beat));
+
+        // Move on to the scheduled segment once its first beat has been reached
+        if state
+            .next
+            .as_ref()
+            .is_some_and(|next| clock.time().ticks >= next.start)
+        {
+            state.current = state.next.take();
+            state.transition = false;
+            if let Some(current) = &state.current {
+                segment_started.send(MusicSegmentStarted {
+                    sequencer: entity,
+                    segment: current.name.clone(),
+                });
+            }
+        }
+
+        if !state.transition {
+            if let Some((pending, segment)) = state.take_pending(segments) {
+                let beat = state
+                    .quantize(pending.quantization, segments)
+                    .expect("Sequencer is playing");
+                // Replace the continuation of the current segment
+                if let Some(next) = state.next.take() {
+                    commands.entity(next.entity).despawn_recursive();
+                }
+                let current = state.current.as_ref().expect("Sequencer is playing");
+                if pending.quantization != Quantization::SegmentEnd {
+                    // Let the current segment ring out when it ends naturally, but cut it
+                    // otherwise
+                    if let Ok(Some(mut handle)) = q_handles.get_mut(current.entity) {
+                        handle.stop(Tween {
+                            start_time: at(beat),
+                            ..default()
+                        });
+                    }
+                }
+                let clock = state.clock.as_mut().expect("Sequencer is playing");
+                clock.set_speed(
+                    ClockSpeed::TicksPerMinute(segment.tempo),
+                    instant_tween(at(beat)),
+                );
+                state.next = Some(ScheduledSegment {
+                    name: pending.segment,
+                    entity: spawn_sound(
+                        &mut commands,
+                        entity,
+                        segment.source.clone(),
+                        at(beat),
+                        output,
+                    ),
+                    start: beat,
+                });
+                if let Some(stinger) = pending.stinger {
+                    spawn_sound(&mut commands, entity, stinger, at(beat), output);
+                }
+                state.transition = true;
+            }
+        }
+
+        // Schedule what comes after the current segment ahead of time
+        if state.next.is_none() {
+            let current = state.current.as_ref().expect("Sequencer is playing");
+            let Some(segment) = segments.get(&current.name) else {
+                continue;
+            };
+            let name = segment.next.clone().unwrap_or_else(|| current.name.clone());
+            let Some(next_segment) = segments.get(&name) else {
+                warn!("Unknown music segment {name:?}");
+                continue;
+            };
+            let beat = current.start + segment.length();
+            let clock = state.clock.as_mut().expect("Sequencer is playing");
+            clock.set_speed(
+                ClockSpeed::TicksPerMinute(next_segment.tempo),
+                instant_tween(at(beat)),
+            );
+            state.next = Some(ScheduledSegment {
+                name,
+                entity: spawn_sound(
+                    &mut commands,
+                    entity,
+                    next_segment.source.clone(),
+                    at(beat),
+                    output,
+                ),
+                start: beat,
+            });
+        }
+
+        for (sound, quantization) in std::mem::take(&mut state.stingers) {
+            let beat = state
+                .quantize(quantization, segments)
+                .expect("Sequencer is playing");
+            spawn_sound(&mut commands, entity, sound, at(beat), output);
+        }
+    }
+}
@@ -9,12 +9,17 @@
 
 use bevy::prelude::*;
 use kira::manager::error::PlaySoundError;
-use kira::sound::{FromFileError, PlaybackState, Region};
+use kira::sound::{EndPosition, FromFileError, PlaybackPosition, PlaybackState, Region};
+use kira::tween::Tween;
+use kira::StartTime;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::prelude::{AudioFile, AudioFileHandle, AudioFileLoader};
-use crate::AudioPlaybackSet;
+use crate::prelude::{
+    AudioFile, AudioFileHandle, AudioFileLoader, SoundDropped, Virtualized, VoiceStolen,
+};
+use crate::voices::virtualization::VoiceVirtualization;
+use crate::{AudioPlaybackSet, AudioSourceSetup};
 
 use super::{AudioBundle, AudioHandle, AudioSourcePlugin};
 
@@ -41,13 +46,24 @@ impl Plugin for AudioFilePlugin {
             .add_plugins(AudioSourcePlugin::<AudioFile>::default())
             .add_systems(
                 PostUpdate,
-                on_audio_file_ended.in_set(AudioPlaybackSet::Cleanup),
+                (
+                    on_audio_file_ended.in_set(AudioPlaybackSet::Cleanup),
+                    (
+                        (start_virtual_playback, advance_virtual_playback)
+                            .chain()
+                            .after(VoiceVirtualization)
+                            .before(AudioSourcePlugin::<AudioFile>::release_virtualized),
+                        resume_virtual_playback.after(AudioSourceSetup),
+                    )
+                        .in_set(AudioPlaybackSet::Update),
+                ),
             );
     }
 }
 
 /// Describe how the audio components (and entity) will react to the audio source reaching the
-/// end of the file.
+/// end of the file. This also applies when the voice of the source is stolen (see
+/// [`VoiceStolen`]), or when it is dropped because of the sound capacity (see [`SoundDropped`]).
 #[derive(Debug, Copy, Clone, Component, Default)]
 #[component(storage = "SparseSet")]
 pub enum AudioFileEndBehavior {
@@ -64,6 +80,29 @@ pub enum AudioFileEndBehavior {
     },
 }
 
+fn apply_end_behavior(
+    commands: &mut Commands,
+    entity: Entity,
+    end_behavior: Option<&AudioFileEndBehavior>,
+) {
+    match end_behavior.copied().unwrap_or_default() {
+        AudioFileEndBehavior::Nothing => {}
+        AudioFileEndBehavior::RemoveComponents => {
+            commands
+                .entity(entity)
+                .remove::<(AudioFileBundle, Virtualized, VirtualPlayback)>();
+        }
+        AudioFileEndBehavior::Despawn { recursive } => {
+            if recursive {
+                commands.entity(entity).despawn_recursive();
+            } else {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
 fn on_audio_file_ended(
     mut commands: Commands,
     q_sources: Query<(
@@ -71,23 +110,202 @@ fn on_audio_file_ended(
         &AudioHandle<AudioFileHandle>,
         Option<&AudioFileEndBehavior>,
     )>,
+    q_stolen: Query<
+        (Entity, Option<&AudioFileEndBehavior>),
+        (
+            With<Handle<AudioFile>>,
+            Or<(Added<VoiceStolen>, Added<SoundDropped>)>,
+        ),
+    >,
 ) {
-    for (entity, AudioHandle(handle), end_behavior) in &q_sources {
-        if matches!(handle.playback_state(), PlaybackState::Stopped) {
-            match end_behavior.copied().unwrap_or_default() {
-                AudioFileEndBehavior::Nothing => {}
-                AudioFileEndBehavior::RemoveComponents => {
-                    commands.entity(entity).remove::<AudioFileBundle>();
-                }
-                AudioFileEndBehavior::Despawn { recursive } => {
-                    if recursive {
-                        commands.entity(entity).despawn_recursive();
-                    } else {
-                        commands.entity(entity).despawn();
-                    }
-                }
-            }
+    let ended = q_sources
+        .iter()
+        .filter(|(_, AudioHandle(handle), _)| {
+            matches!(handle.playback_state(), PlaybackState::Stopped)
+        })
+        .map(|(entity, _, end_behavior)| (entity, end_behavior))
+        // Stolen and dropped sources have no handle reporting their sound as stopped
+        .chain(&q_stolen);
+    for (entity, end_behavior) in ended {
+        apply_end_behavior(&mut commands, entity, end_behavior);
+    }
+}
+
+/// Playback of an audio file whose voice is virtual.
+#[derive(Component)]
+struct VirtualPlayback {
+    /// Sound of the file when its voice got virtualized, if it had been created before
+    sound: Option<VirtualSound>,
+    /// Time played since the voice got virtualized, scaled by the playback rate, in seconds
+    elapsed: f64,
+    paused: bool,
+    finished: bool,
+}
+
+/// Playback of the sound of an audio file, as it was when its voice got virtualized.
+#[derive(Clone, Copy)]
+struct VirtualSound {
+    /// Position of the sound, in seconds
+    position: f64,
+    /// Duration of the played region of the file, in seconds
+    duration: f64,
+    reverse: bool,
+}
+
+impl VirtualSound {
+    fn new(handle: &AudioFileHandle, settings: &AudioFileSettings) -> Self {
+        Self {
+            position: handle.position(),
+            duration: handle.duration().as_secs_f64(),
+            reverse: handle.is_reversed(settings),
+        }
+    }
+
+    /// Position the sound would be playing at after playing for `elapsed` seconds, wrapped into
+    /// the loop region like the sound would be, or `None` if the sound would have ended.
+    fn position_after(&self, elapsed: f64, loop_region: Option<Region>) -> Option<f64> {
+        let position = if self.reverse {
+            self.position - elapsed
+        } else {
+            self.position + elapsed
+        };
+        let Some(loop_region) = loop_region else {
+            return (0.0..self.duration).contains(&position).then_some(position);
+        };
+        let seconds = |position| match position {
+            PlaybackPosition::Seconds(seconds) => Some(seconds),
+            // Regions given in samples are left for the sound to wrap when seeking
+            PlaybackPosition::Samples(_) => None,
+        };
+        let loop_start = seconds(loop_region.start);
+        let loop_end = match loop_region.end {
+            EndPosition::EndOfAudio => Some(self.duration),
+            EndPosition::Custom(end) => seconds(end),
+        };
+        let (Some(loop_start), Some(loop_end)) = (loop_start, loop_end) else {
+            return Some(position);
+        };
+        // Sounds only wrap once they reach the end of the loop region in their direction, which
+        // leaves them playing the part of the file before it
+        let wraps = if self.reverse {
+            position < loop_start
+        } else {
+            position >= loop_end
+        };
+        if wraps && loop_end > loop_start {
+            Some(loop_start + (position - loop_start).rem_euclid(loop_end - loop_start))
+        } else {
+            Some(position)
+        }
+    }
+}
+
+/// Starts tracking the playback position of audio files when their voice gets virtualized.
+#[allow(clippy::type_complexity)]
+fn start_virtual_playback(
+    mut commands: Commands,
+    q_virtual: Query<
+        (
+            Entity,
+            &AudioFileSettings,
+            Option<&AudioHandle<AudioFileHandle>>,
+        ),
+        (
+            With<Handle<AudioFile>>,
+            With<Virtualized>,
+            Without<VirtualPlayback>,
+        ),
+    >,
+) {
+    for (entity, settings, handle) in &q_virtual {
+        let playback = match handle {
+            Some(AudioHandle(handle)) => VirtualPlayback {
+                sound: Some(VirtualSound::new(handle, settings)),
+                elapsed: 0.0,
+                paused: matches!(
+                    handle.playback_state(),
+                    PlaybackState::Pausing | PlaybackState::Paused
+                ),
+                finished: matches!(
+                    handle.playback_state(),
+                    PlaybackState::Stopping | PlaybackState::Stopped
+                ),
+            },
+            // Virtualized before the sound was ever created, which starts where the sound will
+            // once created
+            None => VirtualPlayback {
+                sound: None,
+                elapsed: 0.0,
+                paused: settings.start_paused,
+                finished: false,
+            },
+        };
+        commands.entity(entity).insert(playback);
+    }
+}
+
+/// Advances the playback position of virtual audio files, applying their end behavior when
+/// they reach their end.
+fn advance_virtual_playback(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    mut q_virtual: Query<
+        (
+            Entity,
+            &AudioFileSettings,
+            &mut VirtualPlayback,
+            Option<&AudioFileEndBehavior>,
+        ),
+        With<Virtualized>,
+    >,
+) {
+    for (entity, settings, mut playback, end_behavior) in &mut q_virtual {
+        if playback.paused || playback.finished {
+            continue;
+        }
+        playback.elapsed += time.delta_seconds_f64() * settings.playback_rate;
+        // The end of sounds which were never created is only known once they are
+        let ended = playback.sound.is_some_and(|sound| {
+            sound
+                .position_after(playback.elapsed, settings.loop_region)
+                .is_none()
+        });
+        if ended {
+            playback.finished = true;
+            apply_end_behavior(&mut commands, entity, end_behavior);
+        }
+    }
+}
+
+/// Moves the sounds of audio files to their virtual playback position once they are created
+/// again.
+fn resume_virtual_playback(
+    mut commands: Commands,
+    mut q_resumed: Query<
+        (
+            Entity,
+            &AudioFileSettings,
+            &mut AudioHandle<AudioFileHandle>,
+            &VirtualPlayback,
+        ),
+        Without<Virtualized>,
+    >,
+) {
+    for (entity, settings, mut handle, playback) in &mut q_resumed {
+        // Newly created sounds are at their start position
+        let sound = playback
+            .sound
+            .unwrap_or_else(|| VirtualSound::new(&handle, settings));
+        let position = sound.position_after(playback.elapsed, settings.loop_region);
+        match position {
+            Some(position) if !playback.finished => handle.seek_to(position),
+            // Seeking past the end stops the sound
+            _ => handle.seek_to(f64::MAX),
+        }
+        if playback.paused {
+            handle.pause(Tween::default());
         }
+        commands.entity(entity).remove::<VirtualPlayback>();
     }
 }
 
@@ -100,7 +318,7 @@ pub enum AudioFileError {
 }
 
 /// Settings available to the user when instantiating an audio file.
-#[derive(Debug, Component, Deserialize, Serialize)]
+#[derive(Debug, Clone, Component, Deserialize, Serialize)]
 pub struct AudioFileSettings {
     /// By default, sounds will start playing right away when inserted. Setting this to `true`
     /// prevents that.
@@ -120,7 +338,10 @@ pub struct AudioFileSettings {
     pub play_region: Region,
     /// Play the file in reverse (not available for streaming sound files)
     pub reverse: bool,
-    // pub start_time: StartTime, // TODO: Implement with serializable types
+    /// When the sound should start playing. Use a clock time to start the sound with sample
+    /// accuracy. Not serialized, as clock times only make sense for the running audio engine.
+    #[serde(skip)]
+    pub start_time: StartTime,
 }
 
 impl Default for AudioFileSettings {
@@ -133,6 +354,7 @@ impl Default for AudioFileSettings {
             loop_region: None,
             play_region: Region::from(..),
             reverse: false,
+            start_time: StartTime::Immediate,
         }
     }
 }
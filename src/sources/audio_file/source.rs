@@ -4,19 +4,18 @@
 use std::io::Cursor;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use bevy::asset::Asset;
 use bevy::prelude::*;
 use kira::manager::error::PlaySoundError;
-use kira::manager::AudioManager;
 use kira::sound::static_sound::{StaticSoundData, StaticSoundHandle, StaticSoundSettings};
 use kira::sound::streaming::{StreamingSoundData, StreamingSoundHandle, StreamingSoundSettings};
-use kira::sound::{FromFileError, PlaybackRate, PlaybackState, Region};
+use kira::sound::{FromFileError, PlaybackRate, PlaybackState, Region, Sound, SoundData};
 use kira::tween::{Tween, Value};
 use kira::{OutputDestination, StartTime, Volume};
 
-use crate::backend::AudioBackend;
-use crate::prelude::{AudioFileError, AudioFileSettings, AudioSource};
+use crate::prelude::{AudioFileError, AudioFileSettings, AudioSource, SoundPlayer};
 use crate::sources::audio_file;
 
 /// Bevy [`Asset`] implementation that wraps audio data for [`kira`].
@@ -43,11 +42,49 @@ impl AudioSource for AudioFile {
 
     fn create_handle(
         &self,
-        manager: &mut AudioManager<AudioBackend>,
+        manager: &mut SoundPlayer,
         asset_settings: &Self::Settings,
         output_destination: OutputDestination,
     ) -> Result<Self::Handle, Self::Error> {
-        let start_paused = asset_settings.start_paused;
+        let data = self
+            .sound_data(asset_settings, output_destination)
+            .map_err(PlaySoundError::IntoSoundError)?;
+        let duration = data.duration();
+        let raw = match data {
+            AudioFileData::Static(data) => manager
+                .play(data)
+                .map(RawAudioHandleImpl::Static)
+                .map_err(audio_file::play_sound_error_transmute)?,
+            AudioFileData::Streaming(data) => manager
+                .play(data)
+                .map(RawAudioHandleImpl::Streaming)
+                .map_err(audio_file::play_sound_error_cast)?,
+        };
+        Ok(AudioFileHandle::new(raw, duration, asset_settings))
+    }
+}
+
+/// Sound data of an audio file, with the user settings applied.
+enum AudioFileData {
+    Static(StaticSoundData),
+    Streaming(StreamingSoundData<FromFileError>),
+}
+
+impl AudioFileData {
+    fn duration(&self) -> Duration {
+        match self {
+            Self::Static(data) => data.duration(),
+            Self::Streaming(data) => data.duration(),
+        }
+    }
+}
+
+impl AudioFile {
+    fn sound_data(
+        &self,
+        asset_settings: &AudioFileSettings,
+        output_destination: OutputDestination,
+    ) -> Result<AudioFileData, AudioFileError> {
         match self {
             Self::Static(data, kira_settings) => {
                 let settings = (*kira_settings)
@@ -57,24 +94,13 @@ impl AudioSource for AudioFile {
                     .panning(asset_settings.panning)
                     .loop_region(asset_settings.loop_region)
                     .reverse(asset_settings.reverse)
-                    .start_position(asset_settings.play_region.start);
-
-                let static_data = StaticSoundData::from_cursor(Cursor::new(data.clone()))
-                    .map_err(|err| {
-                        PlaySoundError::IntoSoundError(AudioFileError::FromFileError(err))
-                    })?
-                    .with_settings(settings);
-                manager
-                    .play(static_data.slice(asset_settings.play_region))
-                    .map_err(audio_file::play_sound_error_transmute)
-                    .map(|mut handle| {
-                        if start_paused {
-                            handle.pause(Tween::default());
-                        }
-                        handle
-                    })
-                    .map(RawAudioHandleImpl::Static)
-                    .map(AudioFileHandle)
+                    .start_position(asset_settings.play_region.start)
+                    .start_time(asset_settings.start_time);
+
+                let static_data = StaticSoundData::from_cursor(Cursor::new(data.clone()))?
+                    .with_settings(settings)
+                    .slice(asset_settings.play_region);
+                Ok(AudioFileData::Static(static_data))
             }
             Self::Streaming {
                 path,
@@ -85,31 +111,46 @@ impl AudioSource for AudioFile {
                     .volume(asset_settings.volume)
                     .playback_rate(asset_settings.playback_rate)
                     .panning(asset_settings.panning)
-                    .loop_region(asset_settings.loop_region);
-                let streaming_sound_data = StreamingSoundData::from_file(path)
-                    .map_err(|err| {
-                        PlaySoundError::IntoSoundError(AudioFileError::FromFileError(err))
-                    })?
-                    .with_settings(settings);
-                manager
-                    .play(streaming_sound_data.slice(asset_settings.play_region))
-                    .map_err(audio_file::play_sound_error_cast)
-                    .map(|mut handle| {
-                        if start_paused {
-                            handle.pause(Tween::default());
-                        }
-                        handle
-                    })
-                    .map(RawAudioHandleImpl::Streaming)
-                    .map(AudioFileHandle)
+                    .loop_region(asset_settings.loop_region)
+                    .start_time(asset_settings.start_time);
+                let streaming_sound_data = StreamingSoundData::from_file(path)?
+                    .with_settings(settings)
+                    .slice(asset_settings.play_region);
+                Ok(AudioFileData::Streaming(streaming_sound_data))
             }
         }
     }
+
+    /// Creates the sound of this audio file without playing it, for it to be processed by
+    /// another sound (e.g. [`LayeredMusic`](crate::prelude::LayeredMusic)). The output
+    /// destination of the sound is not used.
+    pub(crate) fn create_sound(
+        &self,
+        asset_settings: &AudioFileSettings,
+    ) -> Result<(Box<dyn Sound>, AudioFileHandle), AudioFileError> {
+        let data = self.sound_data(asset_settings, OutputDestination::MAIN_TRACK)?;
+        let duration = data.duration();
+        let (sound, raw) = match data {
+            AudioFileData::Static(data) => {
+                let Ok((sound, handle)) = data.into_sound() else {
+                    unreachable!("Creating static sounds cannot fail");
+                };
+                (sound, RawAudioHandleImpl::Static(handle))
+            }
+            AudioFileData::Streaming(data) => {
+                let (sound, handle) = data.into_sound()?;
+                (sound, RawAudioHandleImpl::Streaming(handle))
+            }
+        };
+        Ok((sound, AudioFileHandle::new(raw, duration, asset_settings)))
+    }
 }
 
 /// Handle to an existing audio file. Access this component in your systems to manipulate the
 /// audio in real time (see the `spatial` example to see how to do so).
-pub struct AudioFileHandle(RawAudioHandleImpl);
+///
+/// Dropping the handle (e.g. by despawning its entity) stops the sound.
+pub struct AudioFileHandle(RawAudioHandleImpl, Duration);
 
 macro_rules! defer_call {
     (fn $name:ident(&self $(, $argname:ident: $argtype:ty)*)$( -> $ret:ty)?) => {
@@ -122,8 +163,8 @@ macro_rules! defer_call {
        /// Note: Documentation cannot be provided directly due to limitations with docs in macros.
        pub fn $fnname(&self, $($argname: $argtype),*)$( -> $ret)? {
             match self {
-                Self(RawAudioHandleImpl::Static(handle)) => handle.$name($($argname),*),
-                Self(RawAudioHandleImpl::Streaming(handle)) => handle.$name($($argname),*),
+                Self(RawAudioHandleImpl::Static(handle), _) => handle.$name($($argname),*),
+                Self(RawAudioHandleImpl::Streaming(handle), _) => handle.$name($($argname),*),
             }
         }
     };
@@ -133,8 +174,8 @@ macro_rules! defer_call {
        /// Note: Documentation cannot be provided directly due to limitations with docs in macros.
         pub fn $name(&mut self, $($argname: $argtype),*)$( -> $ret)? {
             match self {
-                Self(RawAudioHandleImpl::Static(handle)) => handle.$name($($argname),*),
-                Self(RawAudioHandleImpl::Streaming(handle)) => handle.$name($($argname),*),
+                Self(RawAudioHandleImpl::Static(handle), _) => handle.$name($($argname),*),
+                Self(RawAudioHandleImpl::Streaming(handle), _) => handle.$name($($argname),*),
             }
         }
     };
@@ -157,6 +198,28 @@ impl AudioFileHandle {
 }
 
 impl AudioFileHandle {
+    fn new(
+        raw: RawAudioHandleImpl,
+        duration: Duration,
+        asset_settings: &AudioFileSettings,
+    ) -> Self {
+        let mut handle = Self(raw, duration);
+        if asset_settings.start_paused {
+            handle.pause(Tween::default());
+        }
+        handle
+    }
+
+    /// Duration of the played region of the file, at a playback rate of 1.
+    pub fn duration(&self) -> Duration {
+        self.1
+    }
+
+    /// Whether the sound plays backwards, which is only possible for static sounds.
+    pub(crate) fn is_reversed(&self, settings: &AudioFileSettings) -> bool {
+        settings.reverse && matches!(self.0, RawAudioHandleImpl::Static(_))
+    }
+
     /// Convenience method to toggling the playback state of an audio file.
     ///
     /// This is a simple wrapper around [`Self::pause`] and [`Self::resume`], which are called
@@ -175,6 +238,12 @@ impl AudioFileHandle {
     }
 }
 
+impl Drop for AudioFileHandle {
+    fn drop(&mut self) {
+        self.stop(Tween::default());
+    }
+}
+
 /// Enum of the possible sound handles that [`kira`] returns
 enum RawAudioHandleImpl {
     Static(StaticSoundHandle),
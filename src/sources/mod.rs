@@ -1,22 +1,35 @@
 //! Implementations of different audio sources.
 use std::fmt;
 use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
 
+use bevy::asset::{LoadState, RecursiveDependencyLoadState};
 use bevy::prelude::*;
+use kira::effect::Effect;
+use kira::manager::error::PlaySoundError;
 use kira::manager::AudioManager;
+use kira::sound::SoundData;
 
 use crate::backend::AudioBackend;
-use crate::spatial::SpatialEmitterHandle;
+use crate::effects::{PendingEffects, SoundDataWithEffects};
+use crate::spatial::{EmitterEffects, EmitterEffectsQuery, SpatialEmitterHandle};
+use crate::tracks::AudioTrackHandle;
+use crate::voices::virtualization::{Virtualized, VoiceVirtualization};
+use crate::voices::{VoiceAllocator, VoiceRegistry, VoiceState, VoiceStolen};
 use crate::{AudioPlaybackSet, AudioSourceSetup, AudioWorld, InternalAudioMarker};
 
 pub mod audio_file;
+pub mod synth;
 
 #[doc(hidden)]
 pub mod prelude {
     pub use super::audio_file::prelude::*;
+    pub use super::synth::prelude::*;
     pub use super::{
-        AudioBundle, AudioHandle, AudioSource, AudioSourcePlugin, NoAudioSettings,
-        OutputDestination,
+        AudioBundle, AudioError, AudioHandle, AudioLoading, AudioPlayFailed, AudioPlayFailedReason,
+        AudioSource, AudioSourcePlugin, NoAudioSettings, OutputDestination, SoundDropped,
+        SoundLimitBehavior, SoundPlayer,
     };
 }
 
@@ -27,7 +40,7 @@ pub mod prelude {
 /// 1. An implementation of [`kira::sound::Sound`] which is going to be sent to the audio engine to
 ///    generate audio samples
 /// 2. A handle which sets up communication between the aforementioned sound and the rest of the
-/// world.
+///    world.
 ///
 /// The trait supports a `Settings` struct, which allows users to customize the sound that will
 /// be sent before its creation.
@@ -42,14 +55,175 @@ pub trait AudioSource: Asset {
     type Settings: Send + Sync + Default + Component;
 
     /// Create an audio handle by calling the manager to play the sound data.
+    ///
+    /// Sounds must be played with [`SoundPlayer::play`] for the effects requested by other
+    /// components (e.g. meters) to be applied to them.
     fn create_handle(
         &self,
-        manager: &mut AudioManager<AudioBackend>,
+        manager: &mut SoundPlayer,
         settings: &Self::Settings,
         output_destination: kira::OutputDestination,
     ) -> Result<Self::Handle, Self::Error>;
 }
 
+/// Access to the audio manager given to [`AudioSource::create_handle`].
+///
+/// It dereferences to the [`AudioManager`] to give access to its full API, but sounds should be
+/// played through [`Self::play`], which inserts the effects requested for this source into its
+/// signal path.
+pub struct SoundPlayer<'a> {
+    manager: &'a mut AudioManager<AudioBackend>,
+    effects: Vec<Box<dyn Effect>>,
+    voice: Option<Arc<VoiceState>>,
+    voice_played: bool,
+    emitter: EmitterEffects,
+    sound_limit_reached: bool,
+}
+
+impl<'a> SoundPlayer<'a> {
+    pub(crate) fn new(
+        manager: &'a mut AudioManager<AudioBackend>,
+        effects: Vec<Box<dyn Effect>>,
+        voice: Arc<VoiceState>,
+        emitter: EmitterEffects,
+    ) -> Self {
+        Self {
+            manager,
+            effects,
+            voice: Some(voice),
+            voice_played: false,
+            emitter,
+            sound_limit_reached: false,
+        }
+    }
+
+    /// Plays a sound, applying the effects requested for this source to it.
+    ///
+    /// Effects are only applied to the first sound played this way, which is also the one
+    /// stopped when the voice of the source is stolen (see
+    /// [`VoiceLimit`](crate::prelude::VoiceLimit)), and the one the effects of the spatial
+    /// emitter (e.g. [`Doppler`](crate::prelude::Doppler)) are applied to.
+    pub fn play<D: SoundData>(
+        &mut self,
+        sound_data: D,
+    ) -> Result<D::Handle, PlaySoundError<D::Error>> {
+        // Kira drops the sound data when the limit is reached, check beforehand to keep the
+        // effects around for a later retry
+        if self.manager.num_sounds() >= self.manager.sound_capacity() {
+            self.sound_limit_reached = true;
+            return Err(PlaySoundError::SoundLimitReached);
+        }
+        let voice = self.voice.take();
+        let has_voice = voice.is_some();
+        let result = self.manager.play(SoundDataWithEffects {
+            data: sound_data,
+            effects: std::mem::take(&mut self.effects),
+            voice,
+            emitter: std::mem::take(&mut self.emitter),
+        });
+        self.voice_played |= has_voice && result.is_ok();
+        result
+    }
+
+    /// Returns the effects which have not been inserted into a sound, to keep them for later.
+    pub(crate) fn into_effects(self) -> Vec<Box<dyn Effect>> {
+        self.effects
+    }
+
+    /// Returns `true` if the voice of the source has been given to a sound which is now playing.
+    pub(crate) fn voice_played(&self) -> bool {
+        self.voice_played
+    }
+
+    /// Returns `true` if a sound could not be played because the sound capacity was reached.
+    pub(crate) fn sound_limit_reached(&self) -> bool {
+        self.sound_limit_reached
+    }
+}
+
+impl<'a> Deref for SoundPlayer<'a> {
+    type Target = AudioManager<AudioBackend>;
+
+    fn deref(&self) -> &Self::Target {
+        self.manager
+    }
+}
+
+impl<'a> DerefMut for SoundPlayer<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.manager
+    }
+}
+
+/// What to do with audio sources that cannot be played because the sound capacity of the audio
+/// engine is reached (see [`AudioPlugin::capacities`](crate::AudioPlugin::capacities)).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Resource)]
+pub enum SoundLimitBehavior {
+    /// Try playing the source again on the next frame, until there is room for it. This is the
+    /// default.
+    #[default]
+    Retry,
+    /// Give up on playing the source, and send an [`AudioPlayFailed`] event. The source is marked
+    /// with [`SoundDropped`].
+    Drop,
+}
+
+/// Event sent when an audio source could not be played.
+#[derive(Debug, Clone, Event)]
+pub struct AudioPlayFailed {
+    /// Entity of the audio source.
+    pub entity: Entity,
+    /// Reason the source could not be played.
+    pub reason: AudioPlayFailedReason,
+}
+
+/// Reason for an audio source to fail playing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioPlayFailedReason {
+    /// The maximum number of sounds playing at once was reached.
+    SoundLimitReached,
+    /// The source returned an error when creating its sound. The entity is marked with an
+    /// [`AudioError`] component holding the error.
+    Error(String),
+    /// The asset of the source, or one of its dependencies, failed to load. The entity is marked
+    /// with an [`AudioError`] component holding the error.
+    LoadFailed(String),
+}
+
+/// Component added to audio sources which returned an error when creating their sound, or whose
+/// asset failed to load, holding the error message. The source is not created again while this
+/// component is present; remove it to try again.
+#[derive(Debug, Clone, PartialEq, Eq, Component)]
+pub struct AudioError(pub String);
+
+/// Marker component added to audio sources waiting for their asset to load.
+#[derive(Debug, Copy, Clone, Component)]
+#[component(storage = "SparseSet")]
+pub struct AudioLoading;
+
+/// Marker component added to audio sources which were not played because the sound capacity of
+/// the audio engine was reached, with [`SoundLimitBehavior::Drop`].
+///
+/// Audio files apply their [`AudioFileEndBehavior`](crate::prelude::AudioFileEndBehavior) as if
+/// they had finished playing. Sources are not created again while marked; remove this component
+/// to try playing them again.
+#[derive(Debug, Copy, Clone, Component)]
+#[component(storage = "SparseSet")]
+pub struct SoundDropped;
+
+/// Returns the error of the asset, or of its dependencies, if it failed to load.
+fn load_error<T: Asset>(asset_server: &AssetServer, source: &Handle<T>) -> Option<String> {
+    match asset_server.get_load_state(source)? {
+        LoadState::Failed(err) => Some(err.to_string()),
+        _ if asset_server.get_recursive_dependency_load_state(source)
+            == Some(RecursiveDependencyLoadState::Failed) =>
+        {
+            Some("A dependency of the asset failed to load".to_string())
+        }
+        _ => None,
+    }
+}
+
 /// Dummy struct for cases where the audio source has no settings.
 #[derive(Debug, Default, Component)]
 pub struct NoAudioSettings;
@@ -72,22 +246,56 @@ impl<T> Default for AudioSourcePlugin<T> {
 
 impl<T: AudioSource> Plugin for AudioSourcePlugin<T> {
     fn build(&self, app: &mut App) {
-        app.init_asset::<T>().add_systems(
-            PostUpdate,
-            Self::audio_added
-                .in_set(AudioPlaybackSet::Update)
-                .in_set(AudioSourceSetup),
-        );
+        app.init_asset::<T>()
+            .add_event::<AudioPlayFailed>()
+            .add_systems(
+                PostUpdate,
+                (
+                    Self::audio_added
+                        .in_set(AudioPlaybackSet::Update)
+                        .in_set(AudioSourceSetup),
+                    Self::release_virtualized
+                        .in_set(AudioPlaybackSet::Update)
+                        .after(VoiceVirtualization)
+                        .before(AudioSourceSetup),
+                    Self::release_removed
+                        .in_set(AudioPlaybackSet::Update)
+                        .before(AudioSourceSetup),
+                ),
+            );
+        #[cfg(feature = "diagnostics")]
+        app.add_plugins(crate::diagnostics::AudioSourceDiagnosticsPlugin::<T>::default());
     }
 }
 
 /// Possible output destinations for the sound. By default, it will be sent directly to the main
 /// track, but you can send it to custom tracks with optional processing on them instead.
-#[derive(Debug, Default, Component)]
+///
+/// Sounds with a [`SpatialEmitter`](crate::prelude::SpatialEmitter) are sent to their emitter
+/// instead.
+#[derive(Debug, Default, Copy, Clone, Component)]
 pub enum OutputDestination {
     /// Send the audio data to the main track (default)
     #[default]
     MainOutput,
+    /// Send the audio data to the track of the given entity, which must have an
+    /// [`AudioTrack`](crate::prelude::AudioTrack) component.
+    Track(Entity),
+}
+
+impl OutputDestination {
+    /// Resolves this destination into a Kira track, returning `None` if the destination track has
+    /// not been created yet.
+    pub(crate) fn resolve(
+        &self,
+        manager: &mut AudioManager<AudioBackend>,
+        q_tracks: &Query<&AudioTrackHandle>,
+    ) -> Option<kira::track::TrackId> {
+        match self {
+            Self::MainOutput => Some(manager.main_track().id()),
+            Self::Track(entity) => q_tracks.get(*entity).ok().map(|handle| handle.id()),
+        }
+    }
 }
 
 /// [`Bundle`] for easy creation of audio sources.
@@ -117,57 +325,157 @@ impl<T: AudioSource> Default for AudioBundle<T> {
 }
 
 impl<T: AudioSource> AudioSourcePlugin<T> {
-    #[allow(clippy::type_complexity)]
+    #[allow(clippy::type_complexity, clippy::too_many_arguments)]
     fn audio_added(
         mut commands: Commands,
         mut audio_world: ResMut<AudioWorld>,
         asset_server: Res<AssetServer>,
         assets: Res<Assets<T>>,
-        q_added: Query<
+        mut q_added: Query<
             (
                 Entity,
                 &Handle<T>,
                 &T::Settings,
                 Option<&SpatialEmitterHandle>,
+                EmitterEffectsQuery,
                 &OutputDestination,
+                Option<&mut PendingEffects>,
+                Has<AudioLoading>,
+            ),
+            (
+                Without<AudioHandle<T::Handle>>,
+                Without<AudioError>,
+                Without<VoiceStolen>,
+                Without<SoundDropped>,
+                Without<Virtualized>,
             ),
-            Without<AudioHandle<T::Handle>>,
         >,
+        q_tracks: Query<&AudioTrackHandle>,
+        mut voices: VoiceAllocator,
+        sound_limit: Res<SoundLimitBehavior>,
+        mut play_failed: EventWriter<AudioPlayFailed>,
     ) {
-        for (entity, source, settings, spatial_emitter, output_destination) in &q_added {
+        for (
+            entity,
+            source,
+            settings,
+            spatial_emitter,
+            emitter_effects,
+            output,
+            effects,
+            loading,
+        ) in &mut q_added
+        {
+            let Some(asset) = assets.get(source).filter(|_| {
+                asset_server.is_loaded_with_dependencies(source) || !asset_server.is_managed(source)
+            }) else {
+                if let Some(err) = load_error(&asset_server, source) {
+                    error!("Cannot load audio source of {entity:?}: {err}");
+                    commands
+                        .entity(entity)
+                        .remove::<AudioLoading>()
+                        .insert(AudioError(err.clone()));
+                    play_failed.send(AudioPlayFailed {
+                        entity,
+                        reason: AudioPlayFailedReason::LoadFailed(err),
+                    });
+                } else if !loading {
+                    commands.entity(entity).insert(AudioLoading);
+                }
+                continue;
+            };
+            if loading {
+                commands.entity(entity).remove::<AudioLoading>();
+            }
             let output_destination = if let Some(emitter) = spatial_emitter {
                 kira::OutputDestination::Emitter(emitter.0.id())
             } else {
-                let output_handle = match output_destination {
-                    OutputDestination::MainOutput => &*audio_world.audio_manager.main_track(),
+                let Some(track) = output.resolve(&mut audio_world.audio_manager, &q_tracks) else {
+                    debug!("Output track not ready");
+                    continue;
                 };
-                kira::OutputDestination::Track(output_handle.id())
+                kira::OutputDestination::Track(track)
             };
-            let result = match assets.get(source) {
-                Some(asset)
-                    if asset_server.is_loaded_with_dependencies(source)
-                        || !asset_server.is_managed(source) =>
-                {
-                    asset.create_handle(
-                        &mut audio_world.audio_manager,
-                        settings,
-                        output_destination,
-                    )
-                }
-                _ => {
-                    debug!("Asset not ready");
-                    continue;
-                } // Asset not ready, wait
+            let Some(voice) = voices.allocate(entity, source.into(), output) else {
+                continue;
             };
+            let mut effects = effects;
+            let mut player = SoundPlayer::new(
+                &mut audio_world.audio_manager,
+                effects
+                    .as_deref_mut()
+                    .map(|effects| std::mem::take(&mut effects.0))
+                    .unwrap_or_default(),
+                voice.clone(),
+                emitter_effects.effects(),
+            );
+            let result = asset.create_handle(&mut player, settings, output_destination);
+            // Sounds played directly on the audio manager don't carry the voice, which would
+            // never be freed
+            if player.voice_played() {
+                voices.register(entity, source.into(), output, voice);
+            }
+            let sound_limit_reached = player.sound_limit_reached();
+            let remaining_effects = player.into_effects();
+            if let Some(effects) = effects.as_deref_mut() {
+                effects.0 = remaining_effects;
+            }
             let handle = match result {
                 Ok(handle) => handle,
+                Err(_) if sound_limit_reached => {
+                    match *sound_limit {
+                        SoundLimitBehavior::Retry => {
+                            warn_once!(
+                                "Sound limit reached, sounds will be played once others finish"
+                            );
+                        }
+                        SoundLimitBehavior::Drop => {
+                            debug!("Sound limit reached, dropping sound of {entity:?}");
+                            commands.entity(entity).insert(SoundDropped);
+                            play_failed.send(AudioPlayFailed {
+                                entity,
+                                reason: AudioPlayFailedReason::SoundLimitReached,
+                            });
+                        }
+                    }
+                    continue;
+                }
                 Err(err) => {
-                    error!("Cannot create handle: {err}");
-                    return;
+                    let message = err.to_string();
+                    error!("Cannot create handle for {entity:?}: {message}");
+                    commands.entity(entity).insert(AudioError(message.clone()));
+                    play_failed.send(AudioPlayFailed {
+                        entity,
+                        reason: AudioPlayFailedReason::Error(message),
+                    });
+                    continue;
                 }
             };
             debug!("Added sound for {} in {entity:?}", T::type_path());
             commands.entity(entity).insert(AudioHandle(handle));
         }
     }
+
+    /// Releases the voices of sources whose handle has been removed, or which have been despawned.
+    fn release_removed(
+        mut registry: ResMut<VoiceRegistry>,
+        mut removed: RemovedComponents<AudioHandle<T::Handle>>,
+    ) {
+        for entity in removed.read() {
+            registry.release(entity);
+        }
+    }
+
+    /// Removes the handles of virtualized sources, which stops their sound.
+    #[allow(clippy::type_complexity)]
+    fn release_virtualized(
+        mut commands: Commands,
+        mut registry: ResMut<VoiceRegistry>,
+        q_virtual: Query<Entity, (With<AudioHandle<T::Handle>>, With<Virtualized>)>,
+    ) {
+        for entity in &q_virtual {
+            registry.release(entity);
+            commands.entity(entity).remove::<AudioHandle<T::Handle>>();
+        }
+    }
 }
@@ -0,0 +1,131 @@
+//! ADSR envelopes shaping the amplitude of synthesizer notes.
+
+/// Attack-decay-sustain-release envelope, shaping the amplitude of the notes of a
+/// [`Synth`](super::Synth).
+///
+/// When a note starts, its amplitude rises to its maximum during the attack, then falls to the
+/// sustain level during the decay, where it stays until the note is released. The amplitude then
+/// falls to silence during the release. All the stages are linear.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Adsr {
+    /// Duration of the attack, in seconds.
+    pub attack: f64,
+    /// Duration of the decay, in seconds.
+    pub decay: f64,
+    /// Amplitude of the note after the decay, between 0 and 1.
+    pub sustain: f64,
+    /// Duration of the release, in seconds.
+    pub release: f64,
+}
+
+impl Default for Adsr {
+    /// Envelope holding the note at full amplitude, with attack and release only long enough to
+    /// avoid clicks.
+    fn default() -> Self {
+        Self {
+            attack: 0.005,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.005,
+        }
+    }
+}
+
+impl Adsr {
+    /// Creates an envelope from the durations of its stages (in seconds), and its sustain level.
+    pub fn new(attack: f64, decay: f64, sustain: f64, release: f64) -> Self {
+        Self {
+            attack,
+            decay,
+            sustain,
+            release,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// State of an [`Adsr`] envelope for the note being played.
+pub(super) struct Envelope {
+    adsr: Adsr,
+    stage: Stage,
+    level: f64,
+    /// Level the release started from
+    release_level: f64,
+}
+
+impl Envelope {
+    pub(super) fn new(adsr: Adsr) -> Self {
+        Self {
+            adsr,
+            stage: Stage::Idle,
+            level: 0.0,
+            release_level: 0.0,
+        }
+    }
+
+    /// Starts the attack of a note, from the current level to avoid clicks on retriggered notes.
+    pub(super) fn note_on(&mut self) {
+        self.stage = Stage::Attack;
+    }
+
+    /// Starts the release of the note.
+    pub(super) fn note_off(&mut self) {
+        if self.stage != Stage::Idle {
+            self.stage = Stage::Release;
+            self.release_level = self.level;
+        }
+    }
+
+    /// Returns `true` when no note is playing, after the release.
+    pub(super) fn is_idle(&self) -> bool {
+        self.stage == Stage::Idle
+    }
+
+    /// Advances the envelope by `dt` seconds and returns its level.
+    pub(super) fn next(&mut self, dt: f64) -> f64 {
+        let sustain = self.adsr.sustain.clamp(0.0, 1.0);
+        match self.stage {
+            Stage::Idle => {}
+            Stage::Attack => {
+                self.level += step(dt, self.adsr.attack);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= (1.0 - sustain) * step(dt, self.adsr.decay);
+                if self.level <= sustain {
+                    self.level = sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => self.level = sustain,
+            Stage::Release => {
+                self.level -= self.release_level * step(dt, self.adsr.release);
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    self.stage = Stage::Idle;
+                }
+            }
+        }
+        self.level
+    }
+}
+
+/// Fraction of a stage lasting `duration` seconds covered in `dt` seconds.
+fn step(dt: f64, duration: f64) -> f64 {
+    if duration > 0.0 {
+        (dt / duration).min(1.0)
+    } else {
+        1.0
+    }
+}
@@ -0,0 +1,172 @@
+//! Audio source implementation for procedural synthesizers.
+//!
+//! Synths generate their audio from an oscillator or a noise generator, shaped by an ADSR
+//! envelope, and need no audio files. This is useful to prototype sounds and for simple sound
+//! effects like UI blips. [`Synth`] assets are created in code and added to [`Assets<Synth>`]:
+//!
+//! ```no_run
+//! # use bevy::prelude::*;
+//! # use bevy_kira_components::prelude::*;
+//! fn blip(mut commands: Commands, mut synths: ResMut<Assets<Synth>>) {
+//!     let synth = Synth::new(Waveform::Square).with_envelope(Adsr::new(0.01, 0.05, 0.5, 0.1));
+//!     commands.spawn(SynthBundle {
+//!         source: synths.add(synth),
+//!         settings: SynthSettings {
+//!             frequency: 880.0,
+//!             note_duration: Some(0.1),
+//!             despawn_when_finished: true,
+//!             ..default()
+//!         },
+//!         ..default()
+//!     });
+//! }
+//! ```
+//!
+//! The [`SynthHandle`](sound::SynthHandle) of playing synths controls their frequency and
+//! amplitude in real time, and triggers and releases their notes.
+use std::convert::Infallible;
+
+use bevy::prelude::*;
+use kira::manager::error::PlaySoundError;
+use kira::OutputDestination;
+
+use crate::prelude::{SoundDropped, VoiceStolen};
+use crate::AudioPlaybackSet;
+
+use super::{AudioBundle, AudioHandle, AudioSource, AudioSourcePlugin, SoundPlayer};
+use envelope::Adsr;
+use sound::SynthData;
+use waveform::Waveform;
+
+pub mod envelope;
+pub mod sound;
+pub mod waveform;
+
+#[doc(hidden)]
+#[allow(missing_docs)]
+pub mod prelude {
+    pub use super::envelope::Adsr;
+    pub use super::sound::SynthHandle;
+    pub use super::waveform::Waveform;
+    pub use super::{Synth, SynthBundle, SynthSettings};
+}
+
+/// Specialization of [`AudioBundle`] for the [`Synth`] asset.
+pub type SynthBundle = AudioBundle<Synth>;
+
+/// Implementation of an audio source generating audio procedurally.
+pub struct SynthPlugin;
+
+impl Plugin for SynthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(AudioSourcePlugin::<Synth>::default())
+            .add_systems(
+                PostUpdate,
+                despawn_finished_synths.in_set(AudioPlaybackSet::Cleanup),
+            );
+    }
+}
+
+/// Bevy [`Asset`] describing the sound of a synthesizer: the waveform of its oscillator, and the
+/// envelope of its notes.
+#[derive(Debug, Default, Copy, Clone, Asset, TypePath)]
+pub struct Synth {
+    /// Waveform generated by the oscillator.
+    pub waveform: Waveform,
+    /// Envelope shaping the amplitude of the notes.
+    pub envelope: Adsr,
+}
+
+impl Synth {
+    /// Creates a synth generating the given waveform, with notes held at full amplitude.
+    pub fn new(waveform: Waveform) -> Self {
+        Self {
+            waveform,
+            envelope: Adsr::default(),
+        }
+    }
+
+    /// Sets the envelope of the notes of the synth.
+    pub fn with_envelope(mut self, envelope: Adsr) -> Self {
+        self.envelope = envelope;
+        self
+    }
+}
+
+/// Settings available to the user when instantiating a synth.
+#[derive(Debug, Copy, Clone, Component)]
+pub struct SynthSettings {
+    /// Frequency of the oscillator, in Hz. Defaults to 440 Hz (A4).
+    pub frequency: f64,
+    /// Amplitude of the synth, as a linear factor applied to the waveform. Defaults to 0.25
+    /// (-12 dB), to not blast the ears of the user at full scale.
+    pub amplitude: f64,
+    /// Panning of the synth, where 0 is hard left and 1 is hard right. Defaults to 0.5 (center).
+    pub panning: f64,
+    /// By default, a note starts as soon as the synth is inserted. Setting this to `false` keeps
+    /// the synth silent until [`SynthHandle::note_on`](sound::SynthHandle::note_on) is called.
+    pub note_on: bool,
+    /// Makes the synth one-shot: notes are released after this duration (in seconds), and the
+    /// synth stops once the release is over.
+    pub note_duration: Option<f64>,
+    /// Despawn the entity (and its children) once the synth has stopped, or when its voice is
+    /// stolen (see [`VoiceStolen`]) or dropped because of the sound capacity (see
+    /// [`SoundDropped`]).
+    pub despawn_when_finished: bool,
+}
+
+impl Default for SynthSettings {
+    fn default() -> Self {
+        Self {
+            frequency: 440.0,
+            amplitude: 0.25,
+            panning: 0.5,
+            note_on: true,
+            note_duration: None,
+            despawn_when_finished: false,
+        }
+    }
+}
+
+impl AudioSource for Synth {
+    type Error = PlaySoundError<Infallible>;
+    type Handle = sound::SynthHandle;
+    type Settings = SynthSettings;
+
+    fn create_handle(
+        &self,
+        manager: &mut SoundPlayer,
+        settings: &Self::Settings,
+        output_destination: OutputDestination,
+    ) -> Result<Self::Handle, Self::Error> {
+        manager.play(SynthData {
+            synth: *self,
+            settings: *settings,
+            output_destination,
+        })
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn despawn_finished_synths(
+    mut commands: Commands,
+    q_synths: Query<(Entity, &SynthSettings, &AudioHandle<sound::SynthHandle>)>,
+    q_stolen: Query<
+        (Entity, &SynthSettings),
+        (
+            With<Handle<Synth>>,
+            Or<(Added<VoiceStolen>, Added<SoundDropped>)>,
+        ),
+    >,
+) {
+    let finished = q_synths
+        .iter()
+        .filter(|(_, _, AudioHandle(handle))| handle.is_finished())
+        .map(|(entity, settings, _)| (entity, settings))
+        .chain(&q_stolen);
+    for (entity, settings) in finished {
+        if settings.despawn_when_finished {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Sound generating the audio of synthesizers in the audio engine, and the handle controlling it.
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use kira::clock::clock_info::ClockInfoProvider;
+use kira::modulator::value_provider::ModulatorValueProvider;
+use kira::sound::{Sound, SoundData};
+use kira::tween::{Parameter, Tween, Value};
+use kira::{Frame, OutputDestination};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+
+use super::envelope::Envelope;
+use super::waveform::Oscillator;
+use super::{Synth, SynthSettings};
+
+/// Number of commands that can be sent to a synth between two audio callbacks.
+const COMMAND_CAPACITY: usize = 64;
+
+enum SynthCommand {
+    SetFrequency(Value<f64>, Tween),
+    SetAmplitude(Value<f64>, Tween),
+    SetPanning(Value<f64>, Tween),
+    NoteOn,
+    NoteOff,
+    Stop,
+}
+
+/// Handle to a playing [`Synth`], controlling its notes, frequency and amplitude in real time.
+pub struct SynthHandle {
+    commands: HeapProducer<SynthCommand>,
+    finished: Arc<AtomicBool>,
+}
+
+impl SynthHandle {
+    fn send(&mut self, command: SynthCommand) {
+        if self.commands.push(command).is_err() {
+            error!("Too many commands sent to synth, command ignored");
+        }
+    }
+
+    /// Returns `true` once the synth has stopped, and will not produce sound anymore.
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    /// Sets the frequency of the oscillator, in Hz.
+    pub fn set_frequency(&mut self, frequency: impl Into<Value<f64>>, tween: Tween) {
+        self.send(SynthCommand::SetFrequency(frequency.into(), tween));
+    }
+
+    /// Sets the amplitude of the synth, as a linear factor applied to the waveform.
+    pub fn set_amplitude(&mut self, amplitude: impl Into<Value<f64>>, tween: Tween) {
+        self.send(SynthCommand::SetAmplitude(amplitude.into(), tween));
+    }
+
+    /// Sets the panning of the synth, where 0 is hard left and 1 is hard right.
+    pub fn set_panning(&mut self, panning: impl Into<Value<f64>>, tween: Tween) {
+        self.send(SynthCommand::SetPanning(panning.into(), tween));
+    }
+
+    /// Starts a note, from the attack of the envelope.
+    pub fn note_on(&mut self) {
+        self.send(SynthCommand::NoteOn);
+    }
+
+    /// Releases the current note.
+    pub fn note_off(&mut self) {
+        self.send(SynthCommand::NoteOff);
+    }
+
+    /// Releases the current note, and stops the synth once the release is over. Stopped synths
+    /// cannot be played again.
+    pub fn stop(&mut self) {
+        self.send(SynthCommand::Stop);
+    }
+}
+
+/// Data used to create the sound of a [`Synth`] in the audio engine.
+pub(super) struct SynthData {
+    pub(super) synth: Synth,
+    pub(super) settings: SynthSettings,
+    pub(super) output_destination: OutputDestination,
+}
+
+impl SoundData for SynthData {
+    type Error = Infallible;
+    type Handle = SynthHandle;
+
+    fn into_sound(self) -> Result<(Box<dyn Sound>, Self::Handle), Self::Error> {
+        let (producer, consumer) = HeapRb::new(COMMAND_CAPACITY).split();
+        let finished = Arc::new(AtomicBool::new(false));
+        let mut envelope = Envelope::new(self.synth.envelope);
+        if self.settings.note_on {
+            envelope.note_on();
+        }
+        let sound = SynthSound {
+            output_destination: self.output_destination,
+            commands: consumer,
+            oscillator: Oscillator::new(self.synth.waveform),
+            envelope,
+            frequency: Parameter::new(
+                Value::Fixed(self.settings.frequency),
+                self.settings.frequency,
+            ),
+            amplitude: Parameter::new(
+                Value::Fixed(self.settings.amplitude),
+                self.settings.amplitude,
+            ),
+            panning: Parameter::new(Value::Fixed(self.settings.panning), self.settings.panning),
+            note_duration: self.settings.note_duration,
+            note_remaining: self
+                .settings
+                .note_duration
+                .filter(|_| self.settings.note_on),
+            stopping: false,
+            finished: finished.clone(),
+        };
+        let handle = SynthHandle {
+            commands: producer,
+            finished,
+        };
+        Ok((Box::new(sound), handle))
+    }
+}
+
+struct SynthSound {
+    output_destination: OutputDestination,
+    commands: HeapConsumer<SynthCommand>,
+    oscillator: Oscillator,
+    envelope: Envelope,
+    frequency: Parameter<f64>,
+    amplitude: Parameter<f64>,
+    panning: Parameter<f64>,
+    /// Duration of the notes of one-shot synths, in seconds
+    note_duration: Option<f64>,
+    /// Time left before the current note of a one-shot synth is released
+    note_remaining: Option<f64>,
+    /// The synth stops once the envelope is done releasing
+    stopping: bool,
+    finished: Arc<AtomicBool>,
+}
+
+impl SynthSound {
+    fn note_on(&mut self) {
+        self.envelope.note_on();
+        self.note_remaining = self.note_duration;
+    }
+
+    fn note_off(&mut self) {
+        self.envelope.note_off();
+        self.note_remaining = None;
+        // One-shot synths stop after their note
+        self.stopping |= self.note_duration.is_some();
+    }
+}
+
+impl Sound for SynthSound {
+    fn output_destination(&mut self) -> OutputDestination {
+        self.output_destination
+    }
+
+    fn process(
+        &mut self,
+        dt: f64,
+        clock_info_provider: &ClockInfoProvider,
+        modulator_value_provider: &ModulatorValueProvider,
+    ) -> Frame {
+        while let Some(command) = self.commands.pop() {
+            match command {
+                SynthCommand::SetFrequency(frequency, tween) => {
+                    self.frequency.set(frequency, tween)
+                }
+                SynthCommand::SetAmplitude(amplitude, tween) => {
+                    self.amplitude.set(amplitude, tween)
+                }
+                SynthCommand::SetPanning(panning, tween) => self.panning.set(panning, tween),
+                SynthCommand::NoteOn if !self.stopping => self.note_on(),
+                SynthCommand::NoteOn => {}
+                SynthCommand::NoteOff => self.note_off(),
+                SynthCommand::Stop => {
+                    self.note_off();
+                    self.stopping = true;
+                }
+            }
+        }
+        self.frequency
+            .update(dt, clock_info_provider, modulator_value_provider);
+        self.amplitude
+            .update(dt, clock_info_provider, modulator_value_provider);
+        self.panning
+            .update(dt, clock_info_provider, modulator_value_provider);
+
+        if let Some(remaining) = &mut self.note_remaining {
+            *remaining -= dt;
+            if *remaining <= 0.0 {
+                self.note_off();
+            }
+        }
+        let level = self.envelope.next(dt) * self.amplitude.value();
+        if self.stopping && self.envelope.is_idle() {
+            self.finished.store(true, Ordering::Relaxed);
+        }
+
+        let sample = self.oscillator.next(self.frequency.value() * dt) * level as f32;
+        Frame::from_mono(sample).panned(self.panning.value().clamp(0.0, 1.0) as f32)
+    }
+
+    fn finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,129 @@
+//! Waveforms generated by synthesizers.
+//!
+//! Oscillators with discontinuities (square and saw waves) or corners (triangle waves) are
+//! band-limited with polynomial approximations of band-limited steps (PolyBLEP) and ramps
+//! (PolyBLAMP), which removes most of the aliasing heard in the high frequencies.
+use std::f64::consts::TAU;
+
+/// Waveform generated by a [`Synth`](super::Synth).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Waveform {
+    /// Sine wave, the pure tone of the frequency. This is the default.
+    #[default]
+    Sine,
+    /// Square wave, hollow-sounding with odd harmonics.
+    Square,
+    /// Sawtooth wave, bright-sounding with all harmonics.
+    Saw,
+    /// Triangle wave, softer than the square wave, with odd harmonics.
+    Triangle,
+    /// White noise, with equal power at all frequencies. The frequency is ignored.
+    WhiteNoise,
+    /// Pink noise, with equal power per octave. The frequency is ignored.
+    PinkNoise,
+    /// Brown noise, with most of its power in the low frequencies. The frequency is ignored.
+    BrownNoise,
+}
+
+/// Generator of the samples of a waveform.
+pub(super) struct Oscillator {
+    waveform: Waveform,
+    /// Position in the period of the waveform, between 0 and 1
+    phase: f64,
+    rng: fastrand::Rng,
+    /// State of the pink noise filters
+    pink: [f32; 7],
+    /// State of the brown noise integrator
+    brown: f32,
+}
+
+impl Oscillator {
+    pub(super) fn new(waveform: Waveform) -> Self {
+        Self {
+            waveform,
+            phase: 0.0,
+            rng: fastrand::Rng::new(),
+            pink: [0.0; 7],
+            brown: 0.0,
+        }
+    }
+
+    /// Generates the next sample, advancing the phase by `increment` (the frequency divided by
+    /// the sample rate).
+    pub(super) fn next(&mut self, increment: f64) -> f32 {
+        let increment = increment.clamp(0.0, 0.5);
+        let phase = self.phase;
+        self.phase = (self.phase + increment).fract();
+
+        match self.waveform {
+            Waveform::Sine => (TAU * phase).sin() as f32,
+            Waveform::Square => {
+                let naive = if phase < 0.5 { 1.0 } else { -1.0 };
+                (naive + poly_blep(phase, increment) - poly_blep((phase + 0.5).fract(), increment))
+                    as f32
+            }
+            Waveform::Saw => (2.0 * phase - 1.0 - poly_blep(phase, increment)) as f32,
+            Waveform::Triangle => {
+                // The slope changes by -8 per period on the peak (phase 0), and by 8 on the
+                // trough (phase 0.5)
+                let naive = 4.0 * (phase - 0.5).abs() - 1.0;
+                let slope_change = 8.0 * increment;
+                (naive - slope_change * poly_blamp(phase, increment)
+                    + slope_change * poly_blamp((phase + 0.5).fract(), increment))
+                    as f32
+            }
+            Waveform::WhiteNoise => self.white(),
+            Waveform::PinkNoise => {
+                // Paul Kellett's refined pink noise filter
+                let white = self.white();
+                let b = &mut self.pink;
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.153852;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+                b[6] = white * 0.115926;
+                pink * 0.11
+            }
+            Waveform::BrownNoise => {
+                // Leaky integration of white noise
+                let white = self.white();
+                self.brown = (self.brown + 0.02 * white) / 1.02;
+                self.brown * 3.5
+            }
+        }
+    }
+
+    fn white(&mut self) -> f32 {
+        self.rng.f32() * 2.0 - 1.0
+    }
+}
+
+/// Residual of a band-limited step of height 2 at phase 0, for a phase increasing by `increment`
+/// every sample.
+fn poly_blep(phase: f64, increment: f64) -> f64 {
+    if phase < increment {
+        let t = phase / increment;
+        2.0 * t - t * t - 1.0
+    } else if phase > 1.0 - increment {
+        let t = (phase - 1.0) / increment;
+        t * t + 2.0 * t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Residual of a band-limited ramp at phase 0, for a change of slope of 1 per sample and a phase
+/// increasing by `increment` every sample.
+fn poly_blamp(phase: f64, increment: f64) -> f64 {
+    let distance = if phase < increment {
+        phase / increment
+    } else if phase > 1.0 - increment {
+        (1.0 - phase) / increment
+    } else {
+        return 0.0;
+    };
+    (1.0 - distance).powi(3) / 6.0
+}
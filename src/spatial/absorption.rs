@@ -0,0 +1,130 @@
+//! Absorption of the high frequencies of distant sounds by the air.
+//!
+//! Emitters with an [`AirAbsorption`] are low-pass filtered depending on their distance to the
+//! listener, over the same range of distances as their attenuation ([`EmitterDistances`]): sounds
+//! are unfiltered up to the minimum distance, and filtered down to the far cutoff frequency at the
+//! maximum distance.
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use kira::spatial::emitter::EmitterDistances;
+use kira::tween::Easing;
+use kira::Frame;
+
+use super::factor::{SharedFactor, SmoothedFactor};
+use super::filter::{LowPass, OPEN_CUTOFF};
+use super::listener::primary_listener;
+use crate::prelude::{AudioListener, PrimaryAudioListener, SpatialEmitter, SpatialWorld};
+
+/// Settings of the air absorption of a [`SpatialEmitter`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AirAbsorption {
+    /// Cutoff frequency of the low-pass filter at the maximum distance of the emitter, in Hz.
+    /// Defaults to 2 kHz.
+    pub far_cutoff: f64,
+    /// Curve the filtering follows from the minimum to the maximum distance of the emitter.
+    /// The cutoff frequency is interpolated logarithmically along it. Defaults to linear.
+    pub curve: Easing,
+}
+
+impl Default for AirAbsorption {
+    fn default() -> Self {
+        Self {
+            far_cutoff: 2_000.0,
+            curve: Easing::Linear,
+        }
+    }
+}
+
+/// Component added to spatial emitters with an [`AirAbsorption`], giving how much their sounds are
+/// currently filtered.
+#[derive(Debug, Component)]
+pub struct AirAbsorptionFactor(pub(crate) Arc<AirAbsorptionState>);
+
+impl AirAbsorptionFactor {
+    pub(crate) fn new(absorption: AirAbsorption) -> Self {
+        Self(Arc::new(AirAbsorptionState {
+            factor: SharedFactor::new(0.0),
+            absorption,
+        }))
+    }
+
+    /// How much the sounds of the emitter are filtered, from 0 (at the minimum distance or
+    /// closer) to 1 (at the maximum distance or further).
+    pub fn factor(&self) -> f32 {
+        self.0.factor.get()
+    }
+}
+
+/// Air absorption factor shared between the ECS and the sounds of an emitter.
+#[derive(Debug)]
+pub(crate) struct AirAbsorptionState {
+    factor: SharedFactor,
+    absorption: AirAbsorption,
+}
+
+/// Time for the filtering applied to sounds to follow the computed factor, in seconds.
+const SMOOTHING_TIME: f64 = 0.05;
+
+/// Audio thread side of the air absorption, filtering the sound.
+pub(crate) struct AirAbsorptionProcessor {
+    state: Arc<AirAbsorptionState>,
+    factor: SmoothedFactor,
+    filter: LowPass,
+}
+
+impl AirAbsorptionProcessor {
+    pub(crate) fn new(state: Arc<AirAbsorptionState>) -> Self {
+        let factor = SmoothedFactor::new(&state.factor, SMOOTHING_TIME);
+        Self {
+            state,
+            factor,
+            filter: LowPass::new(),
+        }
+    }
+
+    pub(crate) fn process(&mut self, input: Frame, dt: f64) -> Frame {
+        let factor = self.factor.next(&self.state.factor, dt);
+        if factor < 1e-4 {
+            self.filter.reset(input);
+            return input;
+        }
+
+        let far_cutoff = self.state.absorption.far_cutoff;
+        let cutoff = OPEN_CUTOFF * (far_cutoff / OPEN_CUTOFF).powf(factor);
+        self.filter.process(input, cutoff, dt)
+    }
+}
+
+pub(super) fn update_air_absorption(
+    spatial_world: Res<SpatialWorld>,
+    q_emitters: Query<(&AirAbsorptionFactor, &SpatialEmitter, &GlobalTransform)>,
+    q_listeners: Query<(&GlobalTransform, Has<PrimaryAudioListener>), With<AudioListener>>,
+) {
+    let listener = primary_listener(&q_listeners).map(GlobalTransform::translation);
+    for (factor, emitter, transform) in &q_emitters {
+        let Some(distance) =
+            listener.map(|listener| spatial_world.emitter_distance(emitter, transform, listener))
+        else {
+            factor.0.factor.set(0.0);
+            continue;
+        };
+        let EmitterDistances {
+            min_distance,
+            max_distance,
+        } = emitter.distances;
+        let relative_distance = if max_distance > min_distance {
+            ((distance.clamp(min_distance, max_distance) - min_distance)
+                / (max_distance - min_distance)) as f64
+        } else if distance > min_distance {
+            1.0
+        } else {
+            0.0
+        };
+        let curve = factor.0.absorption.curve;
+        factor
+            .0
+            .factor
+            .set(super::ease(curve, relative_distance).clamp(0.0, 1.0) as f32);
+    }
+}
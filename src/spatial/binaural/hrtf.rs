@@ -0,0 +1,200 @@
+//! Head-related transfer function datasets, as Bevy assets.
+//!
+//! HRTF datasets are loaded from `.hrtf.ron` files, listing the head-related impulse responses
+//! (HRIRs) measured for each direction around the head:
+//!
+//! ```ron
+//! (
+//!     // Sample rate the impulse responses were recorded at
+//!     sample_rate: 48000,
+//!     measurements: [
+//!         (
+//!             // Degrees counter-clockwise from the front, seen from above: 90 is on the left
+//!             azimuth: 0.0,
+//!             // Degrees from the horizontal plane: 90 is above the head
+//!             elevation: 0.0,
+//!             // Impulse responses of each ear, all of the same length
+//!             left: [0.0, 0.8, 0.3, -0.1],
+//!             right: [0.0, 0.8, 0.3, -0.1],
+//!         ),
+//!     ],
+//! )
+//! ```
+//!
+//! Datasets in the SOFA format (like the ones of the CIPIC or LISTEN databases) can be converted
+//! to this format with the SOFA tooling of any scripting language. Keep the impulse responses
+//! short (a few hundred samples at most) and at the sample rate of the audio output: they are
+//! used as they are, and their length directly drives the CPU cost of the convolution.
+use std::sync::Arc;
+
+use bevy::asset::io::Reader;
+use bevy::asset::{ron, AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Impulse responses of both ears for a sound coming from one direction.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct HrtfMeasurement {
+    /// Direction of the sound, in degrees counter-clockwise from the front, seen from above
+    /// (90° is on the left).
+    pub azimuth: f32,
+    /// Direction of the sound, in degrees from the horizontal plane (90° is above the head).
+    pub elevation: f32,
+    /// Impulse response of the left ear.
+    pub left: Vec<f32>,
+    /// Impulse response of the right ear.
+    pub right: Vec<f32>,
+}
+
+impl HrtfMeasurement {
+    /// Direction of the measurement as a unit vector, in the space of the listener (`-Z` in front,
+    /// `+Y` up).
+    fn direction(&self) -> Vec3 {
+        let azimuth = self.azimuth.to_radians();
+        let elevation = self.elevation.to_radians();
+        Vec3::new(
+            -azimuth.sin() * elevation.cos(),
+            elevation.sin(),
+            -azimuth.cos() * elevation.cos(),
+        )
+    }
+}
+
+/// Errors of invalid HRTF datasets.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum HrtfError {
+    /// The dataset has no measurements
+    #[error("The HRTF dataset has no measurements")]
+    NoMeasurements,
+    /// The impulse responses of the dataset have different lengths, or are empty
+    #[error("The impulse responses of the HRTF dataset must all have the same, non-zero length")]
+    InvalidLength,
+}
+
+/// Bevy [`Asset`] holding an HRTF dataset, used to render spatial sounds binaurally (see
+/// [`BinauralSettings`](super::BinauralSettings)).
+#[derive(Debug, Clone, Asset, TypePath)]
+pub struct Hrtf(pub(crate) Arc<HrtfData>);
+
+impl Hrtf {
+    /// Creates a dataset from the measurements of the impulse responses, recorded at the given
+    /// sample rate.
+    pub fn from_measurements(
+        sample_rate: u32,
+        measurements: Vec<HrtfMeasurement>,
+    ) -> Result<Self, HrtfError> {
+        let Some(length) = measurements
+            .first()
+            .map(|measurement| measurement.left.len())
+        else {
+            return Err(HrtfError::NoMeasurements);
+        };
+        if length == 0
+            || measurements.iter().any(|measurement| {
+                measurement.left.len() != length || measurement.right.len() != length
+            })
+        {
+            return Err(HrtfError::InvalidLength);
+        }
+        let directions = measurements
+            .iter()
+            .map(HrtfMeasurement::direction)
+            .collect();
+        Ok(Self(Arc::new(HrtfData {
+            sample_rate,
+            length,
+            measurements,
+            directions,
+        })))
+    }
+
+    /// Sample rate the impulse responses were recorded at. The dataset is only used when it
+    /// matches the sample rate of the audio output.
+    pub fn sample_rate(&self) -> u32 {
+        self.0.sample_rate
+    }
+
+    /// Length of the impulse responses, in samples.
+    pub fn impulse_response_length(&self) -> usize {
+        self.0.length
+    }
+
+    /// Measurements of the dataset.
+    pub fn measurements(&self) -> &[HrtfMeasurement] {
+        &self.0.measurements
+    }
+}
+
+/// Contents of an HRTF dataset, shared with the audio thread.
+#[derive(Debug)]
+pub(crate) struct HrtfData {
+    pub(crate) sample_rate: u32,
+    pub(crate) length: usize,
+    pub(crate) measurements: Vec<HrtfMeasurement>,
+    /// Unit vectors of the direction of each measurement
+    directions: Vec<Vec3>,
+}
+
+impl HrtfData {
+    /// Index of the measurement closest to the direction, given in the space of the listener.
+    pub(crate) fn closest(&self, direction: Vec3) -> usize {
+        let direction = direction.normalize_or_zero();
+        self.directions
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.dot(direction).total_cmp(&b.dot(direction)))
+            .map_or(0, |(index, _)| index)
+    }
+}
+
+/// Loads an [`Hrtf`] dataset from a `.hrtf.ron` file.
+#[derive(Default)]
+pub struct HrtfLoader;
+
+/// Possible errors that can be produced by [`HrtfLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum HrtfLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// The file is not a valid HRTF description
+    #[error("Could not parse the file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    /// The dataset is invalid
+    #[error(transparent)]
+    Hrtf(#[from] HrtfError),
+}
+
+/// Contents of a `.hrtf.ron` file.
+#[derive(Deserialize, Serialize)]
+struct HrtfDescription {
+    sample_rate: u32,
+    measurements: Vec<HrtfMeasurement>,
+}
+
+impl AssetLoader for HrtfLoader {
+    type Asset = Hrtf;
+    type Settings = ();
+    type Error = HrtfLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).await?;
+        let description = ron::de::from_bytes::<HrtfDescription>(&bytes)?;
+        Ok(Hrtf::from_measurements(
+            description.sample_rate,
+            description.measurements,
+        )?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["hrtf.ron"]
+    }
+}
@@ -0,0 +1,451 @@
+//! Binaural rendering of spatial sounds, for headphone users.
+//!
+//! Kira spatializes sounds by panning them between the left and right channels, which gives no
+//! cue of whether a sound is in front or behind the listener, nor of its elevation. When
+//! [`SpatialWorld::binaural`] is set, the sounds of spatial emitters are instead convolved with
+//! the head-related impulse responses of an [`Hrtf`] dataset, picked from the direction of the
+//! emitter relative to the listener.
+//!
+//! Convolution is costly, so only the [`max_voices`](BinauralSettings::max_voices) emitters
+//! nearest to the listener are rendered binaurally; the others fall back to stereo panning.
+//!
+//! ```no_run
+//! # use bevy::prelude::*;
+//! # use bevy_kira_components::prelude::*;
+//! fn setup(mut spatial_world: ResMut<SpatialWorld>, asset_server: Res<AssetServer>) {
+//!     spatial_world.binaural = Some(BinauralSettings::new(asset_server.load("kemar.hrtf.ron")));
+//! }
+//! ```
+//!
+//! Emitters are set up for binaural rendering when they are created, so the settings need to be
+//! present before the emitters are spawned.
+//!
+//! Impulse responses are not resampled: datasets recorded at another sample rate than the one of
+//! the audio output are not used, and sounds are panned instead.
+#![cfg(feature = "hrtf")]
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use bevy::prelude::*;
+use kira::Frame;
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+
+use crate::prelude::{AudioListener, PrimaryAudioListener, SpatialEmitter, SpatialWorld};
+use crate::spatial::factor::{SharedFactor, SmoothedFactor};
+use crate::spatial::listener::primary_listener;
+use crate::voices::virtualization::Virtualized;
+use crate::AudioPlaybackSet;
+use hrtf::{Hrtf, HrtfData, HrtfLoader};
+
+pub mod hrtf;
+
+/// Binaural plugin, registering the HRTF assets and updating the binaural emitters.
+///
+/// It is automatically added by the spatial audio plugin when the `hrtf` feature is enabled.
+pub(crate) struct BinauralPlugin;
+
+impl Plugin for BinauralPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Hrtf>()
+            .init_asset_loader::<HrtfLoader>()
+            .add_systems(
+                PostUpdate,
+                update_binaural_emitters
+                    .in_set(AudioPlaybackSet::Update)
+                    .before(crate::AudioSourceSetup),
+            );
+    }
+}
+
+/// Settings of the binaural rendering of spatial sounds.
+#[derive(Debug, Clone)]
+pub struct BinauralSettings {
+    /// HRTF dataset the sounds are convolved with. Sounds are panned until it is loaded.
+    pub hrtf: Handle<Hrtf>,
+    /// Maximum number of emitters rendered binaurally at once, to cap the CPU usage of the
+    /// convolutions. The emitters nearest to the listener are picked, the others are panned.
+    /// Defaults to 16.
+    pub max_voices: usize,
+}
+
+impl BinauralSettings {
+    /// Creates binaural settings using the given HRTF dataset.
+    pub fn new(hrtf: Handle<Hrtf>) -> Self {
+        Self {
+            hrtf,
+            max_voices: 16,
+        }
+    }
+}
+
+/// Component added to spatial emitters rendered binaurally.
+#[derive(Debug, Component)]
+pub struct BinauralEmitter(pub(crate) Arc<BinauralState>);
+
+impl BinauralEmitter {
+    pub(crate) fn new() -> Self {
+        Self(Arc::default())
+    }
+
+    /// Whether the emitter is currently rendered binaurally, or panned because the HRTF dataset is
+    /// not loaded or the emitter is out of the voice budget.
+    pub fn is_binaural(&self) -> bool {
+        self.0.binaural.load(Ordering::Relaxed)
+    }
+}
+
+/// Binaural rendering state shared between the ECS and the sounds of an emitter.
+#[derive(Debug)]
+pub(crate) struct BinauralState {
+    /// Dataset handed over to the sounds, only locked on the game thread
+    handover: Mutex<DatasetHandover>,
+    /// Index of the measurement of the dataset closest to the direction of the emitter
+    measurement: AtomicU32,
+    binaural: AtomicBool,
+    /// Sample rate of the audio output, as seen by the sounds of the emitter, or 0 until known
+    sample_rate: AtomicU32,
+    /// Panning used instead of the binaural rendering
+    panning: SharedFactor,
+}
+
+impl Default for BinauralState {
+    fn default() -> Self {
+        Self {
+            handover: Mutex::default(),
+            measurement: AtomicU32::new(0),
+            binaural: AtomicBool::new(false),
+            sample_rate: AtomicU32::new(0),
+            panning: SharedFactor::new(0.5),
+        }
+    }
+}
+
+impl BinauralState {
+    /// Hands the dataset over to the sounds of the emitter if it has changed, and drops the
+    /// buffers they are done with.
+    fn set_dataset(&self, dataset: Option<Arc<HrtfData>>) {
+        let mut handover = self.handover.lock().unwrap();
+        handover.collect();
+        let unchanged = match (&handover.dataset, &dataset) {
+            (Some(current), Some(dataset)) => Arc::ptr_eq(current, dataset),
+            (None, None) => true,
+            _ => false,
+        };
+        if unchanged {
+            return;
+        }
+        let measurement = self.measurement.load(Ordering::Relaxed) as usize;
+        for sender in &mut handover.senders {
+            let convolution = dataset
+                .clone()
+                .map(|dataset| Convolution::new(dataset, measurement));
+            if sender.convolutions.push(convolution).is_err() {
+                warn!("Cannot hand the HRTF dataset over to a sound, it is not being processed");
+            }
+        }
+        handover.dataset = dataset;
+    }
+}
+
+/// Number of convolutions in flight between the game thread and each sound, in each direction.
+const HANDOVER_CAPACITY: usize = 4;
+
+/// Dataset of the emitter, and the channels handing its convolution buffers over to its sounds.
+/// The buffers are allocated, and dropped once replaced, on the game thread.
+#[derive(Default)]
+struct DatasetHandover {
+    dataset: Option<Arc<HrtfData>>,
+    senders: Vec<ConvolutionSender>,
+}
+
+impl std::fmt::Debug for DatasetHandover {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DatasetHandover")
+            .field("dataset", &self.dataset.is_some())
+            .field("senders", &self.senders.len())
+            .finish()
+    }
+}
+
+impl DatasetHandover {
+    /// Creates the channels of a new sound, along with the convolution it starts with.
+    fn connect(&mut self, measurement: usize) -> (Option<Convolution>, ConvolutionReceiver) {
+        let (convolutions, convolutions_receiver) = HeapRb::new(HANDOVER_CAPACITY).split();
+        let (retired_sender, retired) = HeapRb::new(HANDOVER_CAPACITY).split();
+        let alive = Arc::new(());
+        self.senders.push(ConvolutionSender {
+            convolutions,
+            retired,
+            alive: Arc::downgrade(&alive),
+        });
+        let convolution = self
+            .dataset
+            .clone()
+            .map(|dataset| Convolution::new(dataset, measurement));
+        let receiver = ConvolutionReceiver {
+            convolutions: convolutions_receiver,
+            retired: retired_sender,
+            _alive: alive,
+        };
+        (convolution, receiver)
+    }
+
+    /// Drops the convolutions replaced by the sounds, and the channels of the sounds which have
+    /// been dropped.
+    fn collect(&mut self) {
+        for sender in &mut self.senders {
+            sender.retired.clear();
+        }
+        self.senders
+            .retain(|sender| sender.alive.strong_count() > 0);
+    }
+}
+
+/// Game thread side of the channels handing convolutions over to a sound.
+struct ConvolutionSender {
+    convolutions: HeapProducer<Option<Convolution>>,
+    retired: HeapConsumer<Option<Convolution>>,
+    /// Dropped along with the sound
+    alive: Weak<()>,
+}
+
+/// Audio thread side of the channels handing convolutions over to a sound.
+struct ConvolutionReceiver {
+    convolutions: HeapConsumer<Option<Convolution>>,
+    retired: HeapProducer<Option<Convolution>>,
+    _alive: Arc<()>,
+}
+
+/// Buffers of the convolution of a sound with the impulse responses of a dataset.
+struct Convolution {
+    dataset: Arc<HrtfData>,
+    /// Last samples of the mono input, written twice for them to be contiguous from any position
+    history: Vec<f32>,
+    /// Impulse responses being convolved, following the ones of the measurement of the emitter
+    left: Vec<f32>,
+    right: Vec<f32>,
+}
+
+impl Convolution {
+    /// Starts from the given measurement instead of fading in from silence.
+    fn new(dataset: Arc<HrtfData>, measurement: usize) -> Self {
+        let (left, right) = dataset
+            .measurements
+            .get(measurement)
+            .map_or((vec![], vec![]), |measurement| {
+                (measurement.left.clone(), measurement.right.clone())
+            });
+        Self {
+            history: vec![0.0; 2 * dataset.length],
+            dataset,
+            left,
+            right,
+        }
+    }
+}
+
+/// Number of samples between updates of the impulse responses being convolved.
+const BLOCK_SIZE: usize = 32;
+/// Time for the impulse responses and the panning to follow the direction of the emitter, in
+/// seconds.
+const SMOOTHING_TIME: f64 = 0.02;
+
+/// Audio thread side of the binaural rendering, convolving the sound with the impulse responses of
+/// its direction.
+pub(crate) struct BinauralProcessor {
+    state: Arc<BinauralState>,
+    receiver: ConvolutionReceiver,
+    convolution: Option<Convolution>,
+    /// Position of the last sample in the history
+    position: usize,
+    block_position: usize,
+    /// Mix between the panned (0) and binaural (1) output
+    mix: f64,
+    sample_rate: u32,
+    panning: SmoothedFactor,
+}
+
+impl BinauralProcessor {
+    /// Created on the game thread, along with the sound.
+    pub(crate) fn new(state: Arc<BinauralState>) -> Self {
+        let measurement = state.measurement.load(Ordering::Relaxed) as usize;
+        let (convolution, receiver) = state.handover.lock().unwrap().connect(measurement);
+        Self {
+            panning: SmoothedFactor::new(&state.panning, SMOOTHING_TIME),
+            state,
+            receiver,
+            convolution,
+            position: 0,
+            block_position: 0,
+            mix: 0.0,
+            sample_rate: 0,
+        }
+    }
+
+    /// Picks up the convolutions handed over by the game thread, sending the ones they replace
+    /// back for them to be dropped there.
+    fn update_dataset(&mut self) {
+        while !self.receiver.retired.is_full() {
+            let Some(convolution) = self.receiver.convolutions.pop() else {
+                return;
+            };
+            let retired = std::mem::replace(&mut self.convolution, convolution);
+            self.position = 0;
+            if self.receiver.retired.push(retired).is_err() {
+                unreachable!("Room for the retired convolution was checked");
+            }
+        }
+    }
+
+    /// Moves the impulse responses being convolved towards the ones of the current measurement.
+    fn update_impulse_responses(&mut self, dt: f64) {
+        let amount = (1.0 - (-(BLOCK_SIZE as f64) * dt / SMOOTHING_TIME).exp()) as f32;
+        let index = self.state.measurement.load(Ordering::Relaxed) as usize;
+        let Some(convolution) = self.convolution.as_mut() else {
+            return;
+        };
+        let Some(measurement) = convolution.dataset.measurements.get(index) else {
+            return;
+        };
+        for (current, target) in convolution.left.iter_mut().zip(&measurement.left) {
+            *current += (target - *current) * amount;
+        }
+        for (current, target) in convolution.right.iter_mut().zip(&measurement.right) {
+            *current += (target - *current) * amount;
+        }
+    }
+
+    /// Whether the impulse responses match the sample rate of the output.
+    fn sample_rate_matches(&self) -> bool {
+        self.convolution
+            .as_ref()
+            .is_some_and(|convolution| convolution.dataset.sample_rate == self.sample_rate)
+    }
+
+    pub(crate) fn process(&mut self, input: Frame, dt: f64) -> Frame {
+        if self.block_position == 0 {
+            let sample_rate = (1.0 / dt).round() as u32;
+            if sample_rate != self.sample_rate {
+                self.sample_rate = sample_rate;
+                self.state.sample_rate.store(sample_rate, Ordering::Relaxed);
+            }
+            self.update_dataset();
+            self.update_impulse_responses(dt);
+        }
+        self.block_position = (self.block_position + 1) % BLOCK_SIZE;
+
+        let amount = 1.0 - (-dt / SMOOTHING_TIME).exp();
+        let length = self
+            .convolution
+            .as_ref()
+            .map_or(0, |convolution| convolution.left.len());
+        let binaural =
+            self.state.binaural.load(Ordering::Relaxed) && length > 0 && self.sample_rate_matches();
+        let target_mix = if binaural { 1.0 } else { 0.0 };
+        self.mix += (target_mix - self.mix) * amount;
+        let panning = self.panning.next(&self.state.panning, dt) as f32;
+
+        let mono = (input.left + input.right) / 2.0;
+        let mut binaural_output = Frame::ZERO;
+        if let Some(convolution) = self.convolution.as_mut().filter(|_| length > 0) {
+            // Write the sample backwards, for the history to read in the order of the impulse
+            // responses. The history is kept up to date while panning, to switch to binaural
+            // rendering without a click.
+            self.position = (self.position + length - 1) % length;
+            convolution.history[self.position] = mono;
+            convolution.history[self.position + length] = mono;
+            // The convolution is skipped while only the panned output is heard
+            if binaural || self.mix >= 1e-4 {
+                let history = &convolution.history[self.position..self.position + length];
+                binaural_output = Frame::new(
+                    dot(history, &convolution.left),
+                    dot(history, &convolution.right),
+                );
+            }
+        }
+        if self.mix > 1.0 - 1e-4 {
+            return binaural_output;
+        }
+
+        // Same panning law as Kira, where the ear facing away from the sound still hears half of it
+        let panned_output = Frame::new(mono * (1.0 - panning / 2.0), mono * (0.5 + panning / 2.0));
+        if self.mix < 1e-4 {
+            return panned_output;
+        }
+        let mix = self.mix as f32;
+        binaural_output * mix + panned_output * (1.0 - mix)
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+#[allow(clippy::type_complexity)]
+fn update_binaural_emitters(
+    spatial_world: Res<SpatialWorld>,
+    hrtfs: Res<Assets<Hrtf>>,
+    q_emitters: Query<(
+        &BinauralEmitter,
+        &SpatialEmitter,
+        &GlobalTransform,
+        Has<Virtualized>,
+    )>,
+    q_listeners: Query<(&GlobalTransform, Has<PrimaryAudioListener>), With<AudioListener>>,
+) {
+    let settings = spatial_world.binaural.as_ref();
+    let dataset = settings
+        .and_then(|settings| hrtfs.get(&settings.hrtf))
+        .map(|hrtf| hrtf.0.clone());
+    let max_voices = settings.map_or(0, |settings| settings.max_voices);
+    let hrtf_sample_rate = dataset.as_ref().map(|dataset| dataset.sample_rate);
+    let listener = primary_listener(&q_listeners).map(|listener| {
+        let (_, rotation, translation) = listener.to_scale_rotation_translation();
+        (translation, rotation)
+    });
+
+    let mut candidates = Vec::new();
+    for (binaural, emitter, transform, is_virtual) in &q_emitters {
+        let state = &binaural.0;
+        state.set_dataset(dataset.clone());
+        let Some((distance, direction)) = listener.map(|(listener, rotation)| {
+            let point = spatial_world.emitter_point(emitter, transform, listener);
+            let offset =
+                spatial_world.audio_position(point) - spatial_world.audio_position(listener);
+            let direction = spatial_world.audio_orientation(rotation).inverse() * offset;
+            (offset.length(), direction)
+        }) else {
+            state.binaural.store(false, Ordering::Relaxed);
+            state.panning.set(0.5);
+            continue;
+        };
+        // Sounds right on the listener are heard from the front
+        let direction = direction.try_normalize().unwrap_or(Vec3::NEG_Z);
+        let panning = 0.5 + direction.x / 2.0;
+        state.panning.set(panning);
+        if let Some(dataset) = &dataset {
+            let measurement = dataset.closest(direction) as u32;
+            state.measurement.store(measurement, Ordering::Relaxed);
+        }
+        // The sample rate of the output is only known once the sounds of the emitter play
+        let sample_rate = state.sample_rate.load(Ordering::Relaxed);
+        let sample_rate_mismatch = hrtf_sample_rate
+            .is_some_and(|hrtf_sample_rate| sample_rate != 0 && sample_rate != hrtf_sample_rate);
+        if sample_rate_mismatch {
+            warn_once!(
+                "HRTF dataset recorded at {} Hz cannot be used with an audio output at {sample_rate} Hz, panning spatial sounds instead",
+                hrtf_sample_rate.unwrap_or_default(),
+            );
+        }
+        if is_virtual || dataset.is_none() || sample_rate_mismatch {
+            state.binaural.store(false, Ordering::Relaxed);
+        } else {
+            candidates.push((distance, state));
+        }
+    }
+
+    candidates.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    for (i, (_, state)) in candidates.into_iter().enumerate() {
+        state.binaural.store(i < max_voices, Ordering::Relaxed);
+    }
+}
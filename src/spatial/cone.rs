@@ -0,0 +1,159 @@
+//! Directivity of spatial emitters, making them louder in front of them.
+//!
+//! Emitters with an [`AudioCone`] sound as usual for listeners within the inner angle of the cone,
+//! and are attenuated (and optionally filtered) for listeners outside of its outer angle, the
+//! effect being interpolated in between. This is useful for speakers, megaphones or voices.
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use kira::{Frame, Volume};
+
+use super::factor::{SharedFactor, SmoothedFactor};
+use super::filter::{LowPass, OPEN_CUTOFF};
+use super::listener::primary_listener;
+use crate::prelude::{AudioListener, PrimaryAudioListener, SpatialWorld};
+
+/// Directivity cone of a [`SpatialEmitter`](crate::prelude::SpatialEmitter).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AudioCone {
+    /// Direction the cone points towards, relative to the rotation of the emitter. Defaults to
+    /// the forward direction of Bevy transforms (`-Z`); 2D games will want a direction within the
+    /// XY plane.
+    pub direction: Vec3,
+    /// Full angle of the cone within which the emitter is heard without change, in radians.
+    /// Defaults to 90°.
+    pub inner_angle: f32,
+    /// Full angle of the cone outside of which the emitter is heard with the outer response, in
+    /// radians. Defaults to 180°.
+    pub outer_angle: f32,
+    /// Volume change outside of the outer angle, in decibels. Defaults to -12 dB.
+    pub outer_volume: f64,
+    /// Cutoff frequency of the low-pass filter applied outside of the outer angle, in Hz. Defaults
+    /// to no filtering.
+    pub outer_cutoff: Option<f64>,
+}
+
+impl Default for AudioCone {
+    fn default() -> Self {
+        Self {
+            direction: Vec3::NEG_Z,
+            inner_angle: PI / 2.0,
+            outer_angle: PI,
+            outer_volume: -12.0,
+            outer_cutoff: None,
+        }
+    }
+}
+
+impl AudioCone {
+    /// How far outside of the cone a listener at the given angle from its direction is, from 0
+    /// (within the inner angle) to 1 (outside of the outer angle).
+    fn factor(&self, angle: f32) -> f32 {
+        let inner = self.inner_angle / 2.0;
+        let outer = self.outer_angle / 2.0;
+        if angle <= inner {
+            0.0
+        } else if angle >= outer {
+            1.0
+        } else {
+            (angle - inner) / (outer - inner)
+        }
+    }
+}
+
+/// Component added to spatial emitters with an [`AudioCone`], giving how far outside of the cone
+/// the listener currently is.
+#[derive(Debug, Component)]
+pub struct ConeFactor(pub(crate) Arc<ConeState>);
+
+impl ConeFactor {
+    pub(crate) fn new(cone: AudioCone) -> Self {
+        Self(Arc::new(ConeState {
+            factor: SharedFactor::new(0.0),
+            cone,
+        }))
+    }
+
+    /// How far outside of the cone the listener is, from 0 (within the inner angle) to 1
+    /// (outside of the outer angle).
+    pub fn factor(&self) -> f32 {
+        self.0.factor.get()
+    }
+}
+
+/// Cone factor shared between the ECS and the sounds of an emitter.
+#[derive(Debug)]
+pub(crate) struct ConeState {
+    factor: SharedFactor,
+    cone: AudioCone,
+}
+
+/// Time for the cone response applied to sounds to follow the computed factor, in seconds.
+const SMOOTHING_TIME: f64 = 0.05;
+
+/// Audio thread side of the cone, attenuating and filtering the sound.
+pub(crate) struct ConeProcessor {
+    state: Arc<ConeState>,
+    factor: SmoothedFactor,
+    filter: LowPass,
+}
+
+impl ConeProcessor {
+    pub(crate) fn new(state: Arc<ConeState>) -> Self {
+        let factor = SmoothedFactor::new(&state.factor, SMOOTHING_TIME);
+        Self {
+            state,
+            factor,
+            filter: LowPass::new(),
+        }
+    }
+
+    pub(crate) fn process(&mut self, input: Frame, dt: f64) -> Frame {
+        let factor = self.factor.next(&self.state.factor, dt);
+        if factor < 1e-4 {
+            self.filter.reset(input);
+            return input;
+        }
+
+        let cone = self.state.cone;
+        let output = match cone.outer_cutoff {
+            Some(outer_cutoff) => {
+                let cutoff = OPEN_CUTOFF * (outer_cutoff / OPEN_CUTOFF).powf(factor);
+                self.filter.process(input, cutoff, dt)
+            }
+            None => input,
+        };
+        output * Volume::Decibels(cone.outer_volume * factor).as_amplitude() as f32
+    }
+}
+
+pub(super) fn update_cones(
+    spatial_world: Res<SpatialWorld>,
+    q_emitters: Query<(&ConeFactor, &GlobalTransform)>,
+    q_listeners: Query<(&GlobalTransform, Has<PrimaryAudioListener>), With<AudioListener>>,
+) {
+    let listener = primary_listener(&q_listeners).map(GlobalTransform::translation);
+    for (factor, transform) in &q_emitters {
+        let (_, rotation, position) = transform.to_scale_rotation_translation();
+        let Some(listener) = listener else {
+            factor.0.factor.set(0.0);
+            continue;
+        };
+        // Projecting the direction like a position keeps it in the plane in 2D
+        let cone = factor.0.cone;
+        let direction = spatial_world.audio_position(rotation * cone.direction);
+        let to_listener =
+            spatial_world.audio_position(listener) - spatial_world.audio_position(position);
+        if direction.length_squared() <= f32::EPSILON
+            || to_listener.length_squared() <= f32::EPSILON
+        {
+            factor.0.factor.set(0.0);
+            continue;
+        }
+        factor
+            .0
+            .factor
+            .set(cone.factor(direction.angle_between(to_listener)));
+    }
+}
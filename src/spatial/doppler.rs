@@ -0,0 +1,164 @@
+//! Doppler effect, shifting the pitch of spatial sources moving relative to the listener.
+//!
+//! The pitch is shifted by changing the rate at which the sounds of the source advance, on top of
+//! the playback rate set on the sounds themselves: a sound playing at twice its speed, and moving
+//! towards the listener, plays even faster. Note that this also speeds up the tweens of the sound.
+use std::sync::Arc;
+
+use bevy::prelude::*;
+
+use super::factor::{SharedFactor, SmoothedFactor};
+use super::listener::primary_listener;
+use crate::prelude::{AudioListener, PrimaryAudioListener, SpatialEmitter, SpatialWorld};
+
+/// Settings of the Doppler effect of a [`SpatialEmitter`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Doppler {
+    /// Speed of sound, in world units per second. Defaults to 343, the speed of sound in air in
+    /// meters per second. Lower it to exaggerate the effect.
+    pub speed_of_sound: f32,
+    /// Lowest factor the pitch can be shifted by. Defaults to 0.5 (an octave down).
+    pub min_factor: f32,
+    /// Highest factor the pitch can be shifted by. Defaults to 2 (an octave up).
+    pub max_factor: f32,
+}
+
+impl Default for Doppler {
+    fn default() -> Self {
+        Self {
+            speed_of_sound: 343.0,
+            min_factor: 0.5,
+            max_factor: 2.0,
+        }
+    }
+}
+
+/// Velocity of a spatial emitter or listener, in world units per second, used for the Doppler
+/// effect.
+///
+/// Without this component, the velocity is derived from the changes of the [`GlobalTransform`]
+/// of the entity between frames. Insert it when the velocity is known (e.g. from physics), or
+/// when the entity teleports.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Component)]
+pub struct AudioVelocity(pub Vec3);
+
+/// Component added to spatial emitters with a Doppler effect, giving the factor their pitch is
+/// currently shifted by.
+#[derive(Debug, Component)]
+pub struct DopplerShift(pub(crate) Arc<SharedFactor>);
+
+impl DopplerShift {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(SharedFactor::new(1.0)))
+    }
+
+    /// Factor the pitch of the sounds of the emitter is shifted by.
+    pub fn factor(&self) -> f32 {
+        self.0.get()
+    }
+}
+
+/// Time for the Doppler factor applied to sounds to follow the computed factor, in seconds.
+/// Smoothes out the steps of factors computed once per frame.
+const SMOOTHING_TIME: f64 = 0.05;
+
+/// Audio thread side of the Doppler effect, smoothing the factor to apply to the time step of the
+/// sound.
+pub(crate) struct DopplerProcessor {
+    state: Arc<SharedFactor>,
+    factor: SmoothedFactor,
+}
+
+impl DopplerProcessor {
+    pub(crate) fn new(state: Arc<SharedFactor>) -> Self {
+        let factor = SmoothedFactor::new(&state, SMOOTHING_TIME);
+        Self { state, factor }
+    }
+
+    /// Advances the smoothing by `dt` seconds, and returns the factor to apply.
+    pub(crate) fn next(&mut self, dt: f64) -> f64 {
+        self.factor.next(&self.state, dt)
+    }
+}
+
+/// Internal component tracking the velocity of entities without [`AudioVelocity`], from the
+/// changes of their position.
+#[derive(Component)]
+pub(crate) struct TrackedVelocity {
+    previous_position: Vec3,
+    velocity: Vec3,
+}
+
+impl TrackedVelocity {
+    pub(crate) fn new(position: Vec3) -> Self {
+        Self {
+            previous_position: position,
+            velocity: Vec3::ZERO,
+        }
+    }
+}
+
+pub(super) fn track_velocities(
+    time: Res<Time>,
+    mut q: Query<(&mut TrackedVelocity, &GlobalTransform)>,
+) {
+    let dt = time.delta_seconds();
+    for (mut tracked, transform) in &mut q {
+        let position = transform.translation();
+        if dt > 0.0 {
+            tracked.velocity = (position - tracked.previous_position) / dt;
+        }
+        tracked.previous_position = position;
+    }
+}
+
+type VelocityQuery<'a> = (
+    &'a GlobalTransform,
+    Option<&'a AudioVelocity>,
+    Option<&'a TrackedVelocity>,
+);
+
+fn velocity((_, velocity, tracked): VelocityQuery) -> Vec3 {
+    velocity
+        .map(|velocity| velocity.0)
+        .or(tracked.map(|tracked| tracked.velocity))
+        .unwrap_or_default()
+}
+
+pub(super) fn update_doppler_shifts(
+    spatial_world: Res<SpatialWorld>,
+    q_emitters: Query<(&SpatialEmitter, &DopplerShift, VelocityQuery)>,
+    q_listeners: Query<(VelocityQuery, Has<PrimaryAudioListener>), With<AudioListener>>,
+) {
+    let listener = primary_listener(&q_listeners);
+    for (emitter, shift, emitter_velocity) in &q_emitters {
+        let Some(doppler) = emitter.doppler else {
+            continue;
+        };
+        let position = emitter_velocity.0.translation();
+        let Some(listener) = listener else {
+            shift.0.set(1.0);
+            continue;
+        };
+        // Only the velocities along the direction in the space of the spatial world count, which
+        // leaves out the depth in 2D
+        let direction = (spatial_world.audio_position(listener.0.translation())
+            - spatial_world.audio_position(position))
+        .normalize_or_zero();
+        let listener_speed = velocity(listener).dot(direction);
+        let emitter_speed = velocity(emitter_velocity).dot(direction);
+        let approach = doppler.speed_of_sound - emitter_speed;
+        let factor = if approach > 0.0 {
+            (doppler.speed_of_sound - listener_speed) / approach
+        } else {
+            // The emitter moves towards the listener faster than sound
+            doppler.max_factor
+        };
+        shift.0.set(
+            factor
+                .max(doppler.min_factor)
+                .min(doppler.max_factor)
+                .max(0.0),
+        );
+    }
+}
@@ -0,0 +1,51 @@
+//! Factors computed once per frame for each emitter, and smoothed by its sounds.
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Factor shared between the ECS and the sounds of an emitter.
+#[derive(Debug)]
+pub(crate) struct SharedFactor {
+    /// Factor stored as the bits of an `f32`
+    bits: AtomicU32,
+}
+
+impl SharedFactor {
+    pub(crate) fn new(factor: f32) -> Self {
+        Self {
+            bits: AtomicU32::new(factor.to_bits()),
+        }
+    }
+
+    pub(crate) fn get(&self) -> f32 {
+        f32::from_bits(self.bits.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set(&self, factor: f32) {
+        self.bits.store(factor.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Audio thread side of a [`SharedFactor`], following it with a one-pole smoothing to smooth out
+/// the steps of factors computed once per frame.
+pub(crate) struct SmoothedFactor {
+    value: f64,
+    /// Time for the value to get `e` times closer to the shared factor, in seconds
+    smoothing_time: f64,
+}
+
+impl SmoothedFactor {
+    /// Starts from the current value of the shared factor.
+    pub(crate) fn new(factor: &SharedFactor, smoothing_time: f64) -> Self {
+        Self {
+            value: factor.get() as f64,
+            smoothing_time,
+        }
+    }
+
+    /// Advances the smoothing by `dt` seconds towards the shared factor, and returns the smoothed
+    /// value.
+    pub(crate) fn next(&mut self, factor: &SharedFactor, dt: f64) -> f64 {
+        let target = factor.get() as f64;
+        self.value += (target - self.value) * (1.0 - (-dt / self.smoothing_time).exp());
+        self.value
+    }
+}
@@ -0,0 +1,36 @@
+//! Filters shared by the spatial effects processing the sounds of emitters.
+use kira::Frame;
+
+/// Cutoff frequency of the low-pass filters when they have no audible effect, in Hz. Cutoff
+/// frequencies are interpolated logarithmically from it.
+pub(crate) const OPEN_CUTOFF: f64 = 20_000.0;
+
+/// Low-pass filter made of two cascaded one-pole filters per channel, for a gentle 12 dB/octave
+/// slope with a cutoff frequency which can change on every frame.
+pub(crate) struct LowPass {
+    stages: [Frame; 2],
+}
+
+impl LowPass {
+    pub(crate) fn new() -> Self {
+        Self {
+            stages: [Frame::ZERO; 2],
+        }
+    }
+
+    /// Makes the filter follow the input while bypassed, for it to start from the current signal
+    /// once enabled again.
+    pub(crate) fn reset(&mut self, input: Frame) {
+        self.stages = [input; 2];
+    }
+
+    pub(crate) fn process(&mut self, input: Frame, cutoff: f64, dt: f64) -> Frame {
+        let coefficient = (1.0 - (-std::f64::consts::TAU * cutoff * dt).exp()) as f32;
+        let mut output = input;
+        for stage in &mut self.stages {
+            *stage = *stage + (output - *stage) * coefficient;
+            output = *stage;
+        }
+        output
+    }
+}
@@ -0,0 +1,22 @@
+//! Lookup of the audio listener sounds are heard by.
+//!
+//! Kira mixes together the sounds heard by each of its listeners, so only one listener is given
+//! to it, and all the spatial effects are computed for that same listener.
+
+/// Picks the listener sounds are heard by among the given ones, each paired with whether it is
+/// marked with [`PrimaryAudioListener`](super::PrimaryAudioListener): the primary listener, or
+/// else the first one found.
+///
+/// Meant to be given queries of listeners with a `Has<PrimaryAudioListener>` as their last item.
+pub(crate) fn primary_listener<T>(listeners: impl IntoIterator<Item = (T, bool)>) -> Option<T> {
+    let mut first = None;
+    for (listener, is_primary) in listeners {
+        if is_primary {
+            return Some(listener);
+        }
+        if first.is_none() {
+            first = Some(listener);
+        }
+    }
+    first
+}
@@ -0,0 +1,633 @@
+//! Support for spatial audio through `kira`'s spatial features.
+//!
+//! Positions are taken in 3D by default. 2D games can switch the [`SpatialWorld`] to
+//! [`SpatialMode::Planar2d`], which ignores the depth of the entities and the orientation of the
+//! listener (typically the camera, far away on the Z axis):
+//!
+//! ```no_run
+//! # use bevy::prelude::*;
+//! # use bevy_kira_components::prelude::*;
+//! fn setup(mut commands: Commands, mut spatial_world: ResMut<SpatialWorld>) {
+//!     spatial_world.mode = SpatialMode::Planar2d {
+//!         scale_by_zoom: true,
+//!     };
+//!     commands.spawn((Camera2dBundle::default(), AudioListener, ListenerZoom(1.0)));
+//! }
+//! ```
+use std::sync::Arc;
+
+use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
+use bevy::ecs::query::QueryData;
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+use kira::spatial::emitter::{EmitterDistances, EmitterHandle, EmitterSettings};
+use kira::spatial::listener::{ListenerHandle, ListenerSettings};
+use kira::spatial::scene::{SpatialSceneHandle, SpatialSceneSettings};
+use kira::tween::{Easing, Tween};
+use kira::Volume;
+
+use crate::{AudioPlaybackSet, AudioSourceSetup, AudioWorld, InternalAudioMarker};
+use absorption::{AirAbsorption, AirAbsorptionFactor, AirAbsorptionState};
+#[cfg(feature = "hrtf")]
+use binaural::{BinauralEmitter, BinauralSettings, BinauralState};
+use cone::{AudioCone, ConeFactor, ConeState};
+use doppler::{Doppler, DopplerShift, TrackedVelocity};
+use factor::SharedFactor;
+use listener::primary_listener;
+use occlusion::{Occlusion, OcclusionFactor, OcclusionSettings, OcclusionState};
+use reverb::EnvironmentReverb;
+use shape::EmitterShape;
+use surround::{SurroundEmitter, SurroundState};
+
+pub mod absorption;
+pub mod binaural;
+pub mod cone;
+pub mod doppler;
+mod factor;
+mod filter;
+pub(crate) mod listener;
+pub mod occlusion;
+pub mod relative;
+pub mod reverb;
+pub mod shape;
+pub mod surround;
+
+#[doc(hidden)]
+#[allow(missing_docs)]
+pub mod prelude {
+    pub use super::absorption::{AirAbsorption, AirAbsorptionFactor};
+    #[cfg(feature = "hrtf")]
+    pub use super::binaural::{
+        hrtf::{Hrtf, HrtfMeasurement},
+        BinauralEmitter, BinauralSettings,
+    };
+    pub use super::cone::{AudioCone, ConeFactor};
+    pub use super::doppler::{AudioVelocity, Doppler, DopplerShift};
+    pub use super::occlusion::{
+        AabbOcclusion, AudioOccluder, Occlusion, OcclusionFactor, OcclusionProvider,
+        OcclusionSettings,
+    };
+    pub use super::relative::ListenerRelative;
+    pub use super::reverb::{EnvironmentReverb, ReverbParameters, ReverbZone, ReverbZoneShape};
+    pub use super::shape::EmitterShape;
+    pub use super::surround::SurroundEmitter;
+    pub use super::{
+        AudioListener, ListenerZoom, PrimaryAudioListener, SpatialEmitter, SpatialMode,
+        SpatialWorld,
+    };
+}
+
+/// Spatial audio plugin. This is an internal plugin, useful for some separation of concerns.
+///
+/// It is automatically added by the main [`AudioPlugin`].
+pub(crate) struct SpatialAudioPlugin;
+
+impl Plugin for SpatialAudioPlugin {
+    fn build(&self, app: &mut App) {
+        #[cfg(feature = "hrtf")]
+        app.add_plugins(binaural::BinauralPlugin);
+        app.init_resource::<SpatialWorld>()
+            .init_resource::<OcclusionSettings>()
+            .init_resource::<EnvironmentReverb>()
+            .add_plugins(SpatialDiagnosticsPlugin)
+            .add_systems(
+                PreUpdate,
+                (add_listeners, add_emitters)
+                    .in_set(AudioPlaybackSet::Setup)
+                    .before(AudioSourceSetup),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    (update_listeners, update_emitters).chain(),
+                    (doppler::track_velocities, doppler::update_doppler_shifts)
+                        .chain()
+                        .before(AudioSourceSetup),
+                    occlusion::update_occlusion.before(AudioSourceSetup),
+                    cone::update_cones.before(AudioSourceSetup),
+                    absorption::update_air_absorption.before(AudioSourceSetup),
+                    surround::update_surround_emitters.before(AudioSourceSetup),
+                    reverb::update_reverb_zones,
+                )
+                    .in_set(AudioPlaybackSet::Update),
+            )
+            .add_systems(
+                PostUpdate,
+                relative::update_listener_relative
+                    .after(TransformSystem::TransformPropagate)
+                    .before(AudioPlaybackSet::Update),
+            );
+    }
+}
+
+/// Marker component setting this entity as an audio listener. It must have a [`GlobalTransform`]
+/// attached for the spatial systems to pick it up.
+///
+/// Sounds are only heard by one listener at a time, the [`PrimaryAudioListener`] when there are
+/// several.
+#[derive(Component)]
+pub struct AudioListener;
+
+/// Marker component setting this audio listener as the primary one, when there are several. Sounds
+/// are only heard by the primary listener, which also sets the [`ListenerZoom`], and is followed
+/// by [`ListenerRelative`](relative::ListenerRelative) entities by default. Without it, the first
+/// listener found is used.
+#[derive(Debug, Default, Copy, Clone, Component)]
+pub struct PrimaryAudioListener;
+
+/// Zoom of an audio listener, used by [`SpatialMode::Planar2d`] when scaling distances by the
+/// zoom. Mirror the scale of the orthographic projection of the camera into it: at a scale of 2,
+/// twice as much of the world is visible, and sounds are heard as if they were twice as close.
+/// Defaults to 1.
+#[derive(Debug, Copy, Clone, PartialEq, Component)]
+pub struct ListenerZoom(pub f32);
+
+impl Default for ListenerZoom {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Internal handle to a Kira listener. Used to update the audio listener position.
+#[derive(Component)]
+pub(crate) struct SpatialListenerHandle(ListenerHandle);
+
+/// Marker component setting this entity as a spatial emitter. It must have a [`GlobalTransform`]
+/// attached for the spatial systems to pick it up.
+///
+/// Note that these settings are only used in the setup of the spatial emitter, and not kept in
+/// sync afterwards, with the exception of the [`EmitterShape`] and the [`Doppler`] settings (the
+/// Doppler effect itself still needs to be enabled at setup).
+#[derive(Component)]
+pub struct SpatialEmitter {
+    /// Function describing the attenuation in volume depending on the distance of this emitter
+    /// to the listener.
+    pub attenuation: Option<Easing>,
+    /// Enables the panning effect that depends on the orientation of the listener.
+    pub enable_spatialization: bool,
+    /// Range of distances describing the distance at which the sound will be playing at full
+    /// volume, and the maximum distance at which the sound will be able to be heard.
+    pub distances: EmitterDistances,
+    /// Shape the sounds of this emitter come from. Defaults to a single point. See [`shape`].
+    pub shape: EmitterShape,
+    /// Enables the Doppler effect, shifting the pitch of the sounds of this emitter when it moves
+    /// relative to the listener. Disabled by default.
+    pub doppler: Option<Doppler>,
+    /// Enables the occlusion of the sounds of this emitter by the geometry between it and the
+    /// listener, with the given response. Disabled by default. See [`occlusion`].
+    pub occlusion: Option<Occlusion>,
+    /// Makes this emitter directional, louder in front of it than behind it, following the given
+    /// cone. Disabled by default. See [`cone`].
+    pub cone: Option<AudioCone>,
+    /// Enables the absorption of high frequencies by the air, making the sounds of this emitter
+    /// duller with distance. Disabled by default. See [`absorption`].
+    pub air_absorption: Option<AirAbsorption>,
+}
+
+impl Default for SpatialEmitter {
+    fn default() -> Self {
+        Self {
+            attenuation: Some(Easing::OutPowi(2)),
+            enable_spatialization: true,
+            distances: EmitterDistances::default(),
+            shape: EmitterShape::Point,
+            doppler: None,
+            occlusion: None,
+            cone: None,
+            air_absorption: None,
+        }
+    }
+}
+
+impl SpatialEmitter {
+    /// Amplitude the emitter is attenuated by at the given distance from a listener, following
+    /// what Kira computes on the audio thread.
+    pub(crate) fn attenuation(&self, distance: f32) -> f32 {
+        let Some(easing) = self.attenuation else {
+            return 1.0;
+        };
+        let EmitterDistances {
+            min_distance,
+            max_distance,
+        } = self.distances;
+        let relative_distance = ((distance.clamp(min_distance, max_distance) - min_distance)
+            / (max_distance - min_distance)) as f64;
+        let relative_volume = ease(easing, 1.0 - relative_distance);
+        Volume::Decibels(Volume::MIN_DECIBELS * (1.0 - relative_volume)).as_amplitude() as f32
+    }
+}
+
+/// Applies the easing curve to a value in 0..=1. Kira does not expose this publicly.
+fn ease(easing: Easing, x: f64) -> f64 {
+    let in_out = |x: f64, ease_in: &dyn Fn(f64) -> f64| {
+        let x = x * 2.0;
+        if x < 1.0 {
+            0.5 * ease_in(x)
+        } else {
+            0.5 * (1.0 - ease_in(2.0 - x)) + 0.5
+        }
+    };
+    match easing {
+        Easing::Linear => x,
+        Easing::InPowi(power) => x.powi(power),
+        Easing::OutPowi(power) => 1.0 - (1.0 - x).powi(power),
+        Easing::InOutPowi(power) => in_out(x, &|x| x.powi(power)),
+        Easing::InPowf(power) => x.powf(power),
+        Easing::OutPowf(power) => 1.0 - (1.0 - x).powf(power),
+        Easing::InOutPowf(power) => in_out(x, &|x| x.powf(power)),
+    }
+}
+
+/// States of the spatial effects of an emitter, shared with its sounds which apply them.
+#[derive(Default)]
+pub(crate) struct EmitterEffects {
+    pub(crate) doppler: Option<Arc<SharedFactor>>,
+    pub(crate) occlusion: Option<Arc<OcclusionState>>,
+    pub(crate) cone: Option<Arc<ConeState>>,
+    pub(crate) air_absorption: Option<Arc<AirAbsorptionState>>,
+    #[cfg(feature = "hrtf")]
+    pub(crate) binaural: Option<Arc<BinauralState>>,
+    pub(crate) surround: Option<Arc<SurroundState>>,
+}
+
+/// Query of the components of an emitter holding the states of its spatial effects.
+#[derive(QueryData)]
+pub(crate) struct EmitterEffectsQuery {
+    doppler: Option<&'static DopplerShift>,
+    occlusion: Option<&'static OcclusionFactor>,
+    cone: Option<&'static ConeFactor>,
+    air_absorption: Option<&'static AirAbsorptionFactor>,
+    #[cfg(feature = "hrtf")]
+    binaural: Option<&'static BinauralEmitter>,
+    surround: Option<&'static SurroundEmitter>,
+}
+
+impl EmitterEffects {
+    /// Whether the emitter has no effect to apply to its sounds.
+    pub(crate) fn is_empty(&self) -> bool {
+        let empty = self.doppler.is_none()
+            && self.occlusion.is_none()
+            && self.cone.is_none()
+            && self.air_absorption.is_none()
+            && self.surround.is_none();
+        #[cfg(feature = "hrtf")]
+        let empty = empty && self.binaural.is_none();
+        empty
+    }
+}
+
+impl EmitterEffectsQueryItem<'_> {
+    /// Shares the states of the spatial effects of the emitter with a new sound.
+    pub(crate) fn effects(&self) -> EmitterEffects {
+        EmitterEffects {
+            doppler: self.doppler.map(|doppler| doppler.0.clone()),
+            occlusion: self.occlusion.map(|occlusion| occlusion.0.clone()),
+            cone: self.cone.map(|cone| cone.0.clone()),
+            air_absorption: self
+                .air_absorption
+                .map(|air_absorption| air_absorption.0.clone()),
+            #[cfg(feature = "hrtf")]
+            binaural: self.binaural.map(|binaural| binaural.0.clone()),
+            surround: self.surround.map(|surround| surround.0.clone()),
+        }
+    }
+}
+
+/// Internal Kira handle emitter. Used to update the spatial emitter position.
+#[derive(Component)]
+pub(crate) struct SpatialEmitterHandle(pub(crate) EmitterHandle);
+
+/// How the positions of listeners and emitters are interpreted.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum SpatialMode {
+    /// Full 3D positions, with panning following the orientation of the listener. This is the
+    /// default.
+    #[default]
+    Full3d,
+    /// Positions projected onto the XY plane, for 2D games. Sounds are attenuated by their 2D
+    /// distance to the listener and panned along the X axis, whatever the depth and the
+    /// orientation of the listener.
+    Planar2d {
+        /// Divides distances by the [`ListenerZoom`] of the listener, for sounds to be heard at
+        /// their distance on screen: they get closer as the camera zooms out.
+        scale_by_zoom: bool,
+    },
+}
+
+/// Global data related to spatial handling in the audio engine.
+#[derive(Resource)]
+pub struct SpatialWorld {
+    /// How positions are interpreted. Defaults to [`SpatialMode::Full3d`].
+    pub mode: SpatialMode,
+    /// Renders the sounds of spatial emitters binaurally when set, instead of panning them.
+    /// Disabled by default. See [`binaural`].
+    #[cfg(feature = "hrtf")]
+    pub binaural: Option<BinauralSettings>,
+    pub(crate) spatial_handle: SpatialSceneHandle,
+    /// Zoom of the listener, when scaling 2D distances by it
+    zoom: f32,
+}
+
+impl FromWorld for SpatialWorld {
+    fn from_world(world: &mut World) -> Self {
+        let settings = world
+            .remove_non_send_resource::<SpatialSceneSettings>()
+            .unwrap_or_default();
+        let mut audio_world = world.resource_mut::<AudioWorld>();
+        let spatial_handle = audio_world
+            .audio_manager
+            .add_spatial_scene(settings)
+            .expect("Cannot create audio spatial world");
+        Self {
+            mode: SpatialMode::default(),
+            #[cfg(feature = "hrtf")]
+            binaural: None,
+            spatial_handle,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl SpatialWorld {
+    /// Position of the translation in the space of the spatial world, following its mode.
+    pub fn audio_position(&self, translation: Vec3) -> Vec3 {
+        match self.mode {
+            SpatialMode::Full3d => translation,
+            SpatialMode::Planar2d { scale_by_zoom } => {
+                let position = translation.truncate().extend(0.0);
+                if scale_by_zoom {
+                    position / self.zoom
+                } else {
+                    position
+                }
+            }
+        }
+    }
+
+    /// Whether the new emitters are set up to be rendered binaurally.
+    fn binaural_enabled(&self) -> bool {
+        #[cfg(feature = "hrtf")]
+        return self.binaural.is_some();
+        #[cfg(not(feature = "hrtf"))]
+        return false;
+    }
+
+    /// Orientation of the listener in the space of the spatial world, following its mode.
+    pub(crate) fn audio_orientation(&self, rotation: Quat) -> Quat {
+        match self.mode {
+            SpatialMode::Full3d => rotation,
+            SpatialMode::Planar2d { .. } => Quat::IDENTITY,
+        }
+    }
+
+    /// Distance between the two translations in the space of the spatial world, as used for
+    /// attenuation.
+    pub fn distance(&self, a: Vec3, b: Vec3) -> f32 {
+        self.audio_position(a).distance(self.audio_position(b))
+    }
+
+    /// Translation of the listener projected like [`Self::audio_position`] does, onto the plane at
+    /// the given depth, but kept in world space to be compared with the shapes of the world (e.g.
+    /// emitters, reverb zones or occluders), the listener being usually far away on the Z axis in
+    /// 2D.
+    pub(crate) fn listener_point(&self, listener: Vec3, depth: f32) -> Vec3 {
+        match self.mode {
+            SpatialMode::Full3d => listener,
+            SpatialMode::Planar2d { .. } => listener.truncate().extend(depth),
+        }
+    }
+
+    /// Point of the shape of the emitter heard by the listener at the given translation, in world
+    /// space.
+    pub(crate) fn emitter_point(
+        &self,
+        emitter: &SpatialEmitter,
+        transform: &GlobalTransform,
+        listener: Vec3,
+    ) -> Vec3 {
+        // Look for the closest point within the plane of the emitter
+        let listener = self.listener_point(listener, transform.translation().z);
+        emitter.shape.closest_point(transform, listener)
+    }
+
+    /// Distance of the shape of the emitter to the listener at the given translation, in the
+    /// space of the spatial world.
+    pub(crate) fn emitter_distance(
+        &self,
+        emitter: &SpatialEmitter,
+        transform: &GlobalTransform,
+        listener: Vec3,
+    ) -> f32 {
+        self.distance(listener, self.emitter_point(emitter, transform, listener))
+    }
+
+    /// Position of the emitter in the space of the spatial world, at the point of its shape heard
+    /// by the listener. Without listener, the origin of the emitter is used.
+    fn emitter_audio_position(
+        &self,
+        emitter: &SpatialEmitter,
+        transform: &GlobalTransform,
+        listener: Option<&GlobalTransform>,
+    ) -> Vec3 {
+        let Some(listener) = listener else {
+            return self.audio_position(transform.translation());
+        };
+        let (_, rotation, listener) = listener.to_scale_rotation_translation();
+        let position = self.audio_position(self.emitter_point(emitter, transform, listener));
+        let listener = self.audio_position(listener);
+        if emitter.shape == EmitterShape::Point || position.distance(listener) > 1e-3 {
+            return position;
+        }
+        // Kira pans sounds at the position of the listener as coming from behind both ears;
+        // listeners inside volumes hear them from right in front instead, at full volume
+        listener
+            + self.audio_orientation(rotation)
+                * Vec3::NEG_Z
+                * emitter.distances.min_distance.max(1e-3)
+    }
+}
+
+/// Gives the Kira listener to the listener sounds are heard by, moving it over when the primary
+/// listener changes.
+#[allow(clippy::type_complexity)]
+fn add_listeners(
+    mut commands: Commands,
+    mut spatial_world: ResMut<SpatialWorld>,
+    reverb: Res<EnvironmentReverb>,
+    q_added: Query<(Entity, &GlobalTransform), Added<AudioListener>>,
+    q_listeners: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            Has<SpatialListenerHandle>,
+            Has<PrimaryAudioListener>,
+        ),
+        With<AudioListener>,
+    >,
+) {
+    for (entity, global_transform) in &q_added {
+        commands
+            .entity(entity)
+            .insert(TrackedVelocity::new(global_transform.translation()));
+    }
+
+    let primary = primary_listener(q_listeners.iter().map(
+        |(entity, global_transform, has_handle, is_primary)| {
+            ((entity, global_transform, has_handle), is_primary)
+        },
+    ));
+    // Emitters would be heard once per Kira listener
+    for (entity, _, has_handle, _) in &q_listeners {
+        if has_handle && primary.map(|(primary, _, _)| primary) != Some(entity) {
+            debug!("Remove listener from {entity:?}");
+            commands.entity(entity).remove::<SpatialListenerHandle>();
+        }
+    }
+    let Some((entity, global_transform, false)) = primary else {
+        return;
+    };
+    let (_, quat, position) = global_transform.to_scale_rotation_translation();
+    let audio_position = spatial_world.audio_position(position);
+    let audio_orientation = spatial_world.audio_orientation(quat);
+    // Listeners output to the main track until there is an environment reverb
+    let mut settings = ListenerSettings::new();
+    if let Some(send_track) = reverb.send_track() {
+        settings = settings.track(send_track);
+    }
+    match spatial_world
+        .spatial_handle
+        .add_listener(audio_position, audio_orientation, settings)
+    {
+        Ok(listener) => {
+            debug!("Add listener to {entity:?}");
+            commands
+                .entity(entity)
+                .insert(SpatialListenerHandle(listener));
+        }
+        // The previous listener may not have been removed by the audio thread yet, try again
+        // on the next frame
+        Err(err) => warn_once!("Cannot create spatial audio listener for {entity:?}: {err}"),
+    }
+}
+
+fn add_emitters(
+    mut commands: Commands,
+    mut spatial_world: ResMut<SpatialWorld>,
+    audio_world: Res<AudioWorld>,
+    q: Query<(Entity, &GlobalTransform, &SpatialEmitter), Added<InternalAudioMarker>>,
+    q_listeners: Query<(&GlobalTransform, Has<PrimaryAudioListener>), With<AudioListener>>,
+) {
+    let listener = primary_listener(&q_listeners);
+    for (entity, global_transform, spatial_emitter) in &q {
+        let position =
+            spatial_world.emitter_audio_position(spatial_emitter, global_transform, listener);
+        // Binaural rendering and surround panning replace the panning of Kira, binaural rendering
+        // taking precedence
+        let binaural = spatial_emitter.enable_spatialization && spatial_world.binaural_enabled();
+        let surround = audio_world
+            .surround
+            .as_ref()
+            .filter(|_| spatial_emitter.enable_spatialization && !binaural);
+        let result = spatial_world.spatial_handle.add_emitter(
+            position,
+            EmitterSettings::default()
+                .attenuation_function(spatial_emitter.attenuation)
+                .enable_spatialization(
+                    spatial_emitter.enable_spatialization && !binaural && surround.is_none(),
+                )
+                .distances(spatial_emitter.distances)
+                .persist_until_sounds_finish(true),
+        );
+        debug!("Add emitter to {entity:?}");
+        match result {
+            Ok(emitter) => {
+                let mut entity = commands.entity(entity);
+                entity.insert(SpatialEmitterHandle(emitter));
+                if spatial_emitter.doppler.is_some() {
+                    entity.insert((
+                        DopplerShift::new(),
+                        TrackedVelocity::new(global_transform.translation()),
+                    ));
+                }
+                if let Some(occlusion) = spatial_emitter.occlusion {
+                    entity.insert(OcclusionFactor::new(occlusion));
+                }
+                if let Some(cone) = spatial_emitter.cone {
+                    entity.insert(ConeFactor::new(cone));
+                }
+                if let Some(air_absorption) = spatial_emitter.air_absorption {
+                    entity.insert(AirAbsorptionFactor::new(air_absorption));
+                }
+                #[cfg(feature = "hrtf")]
+                if binaural {
+                    entity.insert(BinauralEmitter::new());
+                }
+                if let Some(bus) = surround {
+                    entity.insert(SurroundEmitter::new(bus.clone()));
+                }
+            }
+            Err(err) => {
+                error!("Cannot create spatial audio emitter for entity {entity:?}: {err}");
+            }
+        }
+    }
+}
+
+fn update_listeners(
+    mut spatial_world: ResMut<SpatialWorld>,
+    mut q: Query<(&mut SpatialListenerHandle, &GlobalTransform)>,
+    q_zooms: Query<(Option<&ListenerZoom>, Has<PrimaryAudioListener>), With<AudioListener>>,
+) {
+    let zoom = primary_listener(&q_zooms)
+        .flatten()
+        .map_or(1.0, |zoom| zoom.0);
+    if zoom > 0.0 && zoom.is_finite() && spatial_world.zoom != zoom {
+        spatial_world.zoom = zoom;
+    }
+    for (mut listener, global_transform) in &mut q {
+        let (_, quat, position) = global_transform.to_scale_rotation_translation();
+        listener
+            .0
+            .set_position(spatial_world.audio_position(position), Tween::default());
+        listener
+            .0
+            .set_orientation(spatial_world.audio_orientation(quat), Tween::default());
+    }
+}
+
+fn update_emitters(
+    spatial_world: Res<SpatialWorld>,
+    mut q: Query<(&mut SpatialEmitterHandle, &SpatialEmitter, &GlobalTransform)>,
+    q_listeners: Query<(&GlobalTransform, Has<PrimaryAudioListener>), With<AudioListener>>,
+) {
+    let listener = primary_listener(&q_listeners);
+    for (mut handle, emitter, global_transform) in &mut q {
+        let position = spatial_world.emitter_audio_position(emitter, global_transform, listener);
+        handle.0.set_position(position, Tween::default());
+    }
+}
+
+/// Bevy diagnostic path recording the number of emitters present.
+pub const SPATIAL_EMITTERS: DiagnosticPath = DiagnosticPath::const_new("kira::spatial::emitters");
+/// Bevy diagnostic path recording the number of listeners present.
+pub const SPATIAL_LISTENERS: DiagnosticPath = DiagnosticPath::const_new("kira::spatial::listeners");
+
+struct SpatialDiagnosticsPlugin;
+
+impl Plugin for SpatialDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_diagnostic(Diagnostic::new(SPATIAL_EMITTERS).with_suffix(" emitters"))
+            .register_diagnostic(Diagnostic::new(SPATIAL_LISTENERS).with_suffix(" listeners"))
+            .add_systems(Last, record_spatial_diagnostics);
+    }
+}
+
+fn record_spatial_diagnostics(
+    mut diagnostics: Diagnostics,
+    q_emitters: Query<(), With<SpatialEmitterHandle>>,
+    q_listeners: Query<(), With<SpatialListenerHandle>>,
+) {
+    diagnostics.add_measurement(&SPATIAL_EMITTERS, || q_emitters.iter().count() as _);
+    diagnostics.add_measurement(&SPATIAL_LISTENERS, || q_listeners.iter().count() as _);
+}
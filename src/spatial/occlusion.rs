@@ -0,0 +1,274 @@
+//! Occlusion of spatial sounds by the geometry between the emitter and the listener.
+//!
+//! The occlusion of each emitter with an [`Occlusion`] response is computed once per frame by the
+//! [`OcclusionProvider`] of the [`OcclusionSettings`] resource, as a factor going from 0 (clear
+//! line of sight) to 1 (fully occluded). The factor drives a low-pass filter and a volume
+//! reduction applied to the sounds of the emitter.
+//!
+//! The default provider, [`AabbOcclusion`], checks the line of sight against axis-aligned boxes
+//! set up with the [`AudioOccluder`] component. Projects using a physics engine can instead
+//! raycast against their colliders by setting a closure as the provider:
+//!
+//! ```no_run
+//! # use bevy::prelude::*;
+//! # use bevy_kira_components::prelude::*;
+//! fn raycast(world: &World, from: Vec3, to: Vec3) -> f32 {
+//!     // Query the physics engine from the world resources here
+//!     0.0
+//! }
+//!
+//! App::new().insert_resource(OcclusionSettings::new(raycast));
+//! ```
+//!
+//! Obstruction, where the direct path is blocked but the sound still reaches the listener around
+//! the obstacle, is best modeled with a response mostly made of filtering; occlusion of sounds in
+//! other rooms with both filtering and a strong volume reduction.
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use kira::{Frame, Volume};
+
+use super::factor::{SharedFactor, SmoothedFactor};
+use super::filter::{LowPass, OPEN_CUTOFF};
+use super::listener::primary_listener;
+use crate::prelude::{AudioListener, PrimaryAudioListener, SpatialEmitter, SpatialWorld};
+
+/// Response of the sounds of a [`SpatialEmitter`] to their occlusion. The values are reached when
+/// the sound is fully occluded, and interpolated from no effect at all for partial occlusion.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Occlusion {
+    /// Volume change of fully occluded sounds, in decibels. Defaults to -15 dB.
+    pub volume: f64,
+    /// Cutoff frequency of the low-pass filter applied to fully occluded sounds, in Hz. Defaults
+    /// to 500 Hz.
+    pub cutoff: f64,
+}
+
+impl Default for Occlusion {
+    fn default() -> Self {
+        Self {
+            volume: -15.0,
+            cutoff: 500.0,
+        }
+    }
+}
+
+/// Computes how much the path of a sound from an emitter to a listener is occluded.
+///
+/// Closures taking the world and the positions of the emitter and the listener implement this
+/// trait.
+pub trait OcclusionProvider: Send + Sync + 'static {
+    /// Called once per frame before computing the occlusion of the emitters, to gather data from
+    /// the world.
+    fn prepare(&mut self, _world: &mut World) {}
+
+    /// Returns how much the path of a sound from `from` (the emitter) to `to` (the listener) is
+    /// occluded, from 0 (clear line of sight) to 1 (fully occluded).
+    fn occlusion(&self, world: &World, from: Vec3, to: Vec3) -> f32;
+}
+
+impl<F: Fn(&World, Vec3, Vec3) -> f32 + Send + Sync + 'static> OcclusionProvider for F {
+    fn occlusion(&self, world: &World, from: Vec3, to: Vec3) -> f32 {
+        self(world, from, to)
+    }
+}
+
+/// Settings of the occlusion of spatial sounds.
+#[derive(Resource)]
+pub struct OcclusionSettings {
+    /// Provider computing the occlusion of the emitters. Defaults to [`AabbOcclusion`].
+    pub provider: Box<dyn OcclusionProvider>,
+}
+
+impl Default for OcclusionSettings {
+    fn default() -> Self {
+        Self::new(AabbOcclusion::default())
+    }
+}
+
+impl OcclusionSettings {
+    /// Creates occlusion settings with the given provider.
+    pub fn new(provider: impl OcclusionProvider) -> Self {
+        Self {
+            provider: Box::new(provider),
+        }
+    }
+}
+
+/// Axis-aligned box occluding sounds, for the built-in [`AabbOcclusion`] provider. The box is
+/// centered on the [`GlobalTransform`] of the entity, and scaled by it; its rotation is ignored.
+#[derive(Debug, Copy, Clone, PartialEq, Component)]
+pub struct AudioOccluder {
+    /// Half of the size of the box on each axis.
+    pub half_extents: Vec3,
+    /// How much the sound going through the box is occluded, from 0 to 1. Defaults to 1.
+    pub absorption: f32,
+}
+
+impl Default for AudioOccluder {
+    fn default() -> Self {
+        Self {
+            half_extents: Vec3::splat(0.5),
+            absorption: 1.0,
+        }
+    }
+}
+
+impl AudioOccluder {
+    /// Creates a fully absorbing occluder of the given size.
+    pub fn new(size: Vec3) -> Self {
+        Self {
+            half_extents: size / 2.0,
+            ..default()
+        }
+    }
+}
+
+/// Occlusion provider checking the line of sight against [`AudioOccluder`] boxes. The
+/// absorption of all the boxes crossed by the line of sight adds up.
+#[derive(Debug, Default)]
+pub struct AabbOcclusion {
+    /// Occluders in the world, as minimum and maximum corners and absorption
+    occluders: Vec<(Vec3, Vec3, f32)>,
+}
+
+impl OcclusionProvider for AabbOcclusion {
+    fn prepare(&mut self, world: &mut World) {
+        self.occluders.clear();
+        let mut q_occluders = world.query::<(&AudioOccluder, &GlobalTransform)>();
+        for (occluder, transform) in q_occluders.iter(world) {
+            let (scale, _, center) = transform.to_scale_rotation_translation();
+            let half_extents = occluder.half_extents * scale.abs();
+            self.occluders.push((
+                center - half_extents,
+                center + half_extents,
+                occluder.absorption.clamp(0.0, 1.0),
+            ));
+        }
+    }
+
+    fn occlusion(&self, _world: &World, from: Vec3, to: Vec3) -> f32 {
+        let transmission = self
+            .occluders
+            .iter()
+            .filter(|(min, max, _)| segment_intersects_box(from, to, *min, *max))
+            .map(|(_, _, absorption)| 1.0 - absorption)
+            .product::<f32>();
+        1.0 - transmission
+    }
+}
+
+/// Slab test of the segment against the box.
+fn segment_intersects_box(from: Vec3, to: Vec3, min: Vec3, max: Vec3) -> bool {
+    let direction = to - from;
+    let mut t_min = 0f32;
+    let mut t_max = 1f32;
+    for axis in 0..3 {
+        if direction[axis].abs() < f32::EPSILON {
+            if from[axis] < min[axis] || from[axis] > max[axis] {
+                return false;
+            }
+            continue;
+        }
+        let t1 = (min[axis] - from[axis]) / direction[axis];
+        let t2 = (max[axis] - from[axis]) / direction[axis];
+        t_min = t_min.max(t1.min(t2));
+        t_max = t_max.min(t1.max(t2));
+        if t_min > t_max {
+            return false;
+        }
+    }
+    true
+}
+
+/// Component added to spatial emitters with an [`Occlusion`] response, giving how much their
+/// sounds are currently occluded.
+#[derive(Debug, Component)]
+pub struct OcclusionFactor(pub(crate) Arc<OcclusionState>);
+
+impl OcclusionFactor {
+    pub(crate) fn new(response: Occlusion) -> Self {
+        Self(Arc::new(OcclusionState {
+            factor: SharedFactor::new(0.0),
+            response,
+        }))
+    }
+
+    /// How much the sounds of the emitter are occluded, from 0 to 1.
+    pub fn factor(&self) -> f32 {
+        self.0.factor.get()
+    }
+}
+
+/// Occlusion factor shared between the ECS and the sounds of an emitter.
+#[derive(Debug)]
+pub(crate) struct OcclusionState {
+    factor: SharedFactor,
+    response: Occlusion,
+}
+
+/// Time for the occlusion applied to sounds to follow the computed factor, in seconds.
+const SMOOTHING_TIME: f64 = 0.1;
+
+/// Audio thread side of the occlusion, filtering and attenuating the sound.
+pub(crate) struct OcclusionProcessor {
+    state: Arc<OcclusionState>,
+    factor: SmoothedFactor,
+    filter: LowPass,
+}
+
+impl OcclusionProcessor {
+    pub(crate) fn new(state: Arc<OcclusionState>) -> Self {
+        let factor = SmoothedFactor::new(&state.factor, SMOOTHING_TIME);
+        Self {
+            state,
+            factor,
+            filter: LowPass::new(),
+        }
+    }
+
+    pub(crate) fn process(&mut self, input: Frame, dt: f64) -> Frame {
+        let factor = self.factor.next(&self.state.factor, dt);
+        if factor < 1e-4 {
+            self.filter.reset(input);
+            return input;
+        }
+
+        let response = self.state.response;
+        let cutoff = OPEN_CUTOFF * (response.cutoff / OPEN_CUTOFF).powf(factor);
+        let output = self.filter.process(input, cutoff, dt);
+        output * Volume::Decibels(response.volume * factor).as_amplitude() as f32
+    }
+}
+
+pub(super) fn update_occlusion(world: &mut World) {
+    world.resource_scope(|world, mut settings: Mut<OcclusionSettings>| {
+        settings.provider.prepare(world);
+        let listener = primary_listener(
+            world
+                .query_filtered::<(&GlobalTransform, Has<PrimaryAudioListener>), With<AudioListener>>()
+                .iter(world),
+        )
+        .map(GlobalTransform::translation);
+        let mut q_emitters = world.query::<(&OcclusionFactor, &SpatialEmitter, &GlobalTransform)>();
+        let spatial_world = world.resource::<SpatialWorld>();
+        for (factor, emitter, transform) in q_emitters.iter(world) {
+            let Some(listener) = listener else {
+                factor.0.factor.set(0.0);
+                continue;
+            };
+            // Sounds are occluded from the point of the emitter they hear
+            let position = spatial_world.emitter_point(emitter, transform, listener);
+            // The path of the sound stays within the plane of the emitter in 2D
+            let listener = spatial_world.listener_point(listener, position.z);
+            let occlusion = settings.provider.occlusion(world, position, listener);
+            // A NaN would corrupt the state of the filter of the sounds for good
+            let occlusion = if occlusion.is_finite() {
+                occlusion.clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            factor.0.factor.set(occlusion);
+        }
+    });
+}
@@ -0,0 +1,76 @@
+//! Sounds following an audio listener, like the breathing of the player or the UI in VR.
+//!
+//! Entities with a [`ListenerRelative`] component have their [`Transform`] read relative to the
+//! listener instead of the world: a translation of `-Z` keeps the entity in front of the
+//! listener, wherever it goes and looks. Their [`GlobalTransform`] is overwritten accordingly
+//! after transform propagation, which all the spatial systems pick up, without having to parent
+//! the entity to the listener.
+//!
+//! ```no_run
+//! # use bevy::prelude::*;
+//! # use bevy_kira_components::prelude::*;
+//! fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+//!     commands.spawn((
+//!         AudioFileBundle {
+//!             source: asset_server.load("radio.ogg"),
+//!             ..default()
+//!         },
+//!         SpatialEmitter::default(),
+//!         ListenerRelative::default(),
+//!         // Slightly to the left of the listener
+//!         TransformBundle::from_transform(Transform::from_xyz(-0.5, 0.0, 0.0)),
+//!     ));
+//! }
+//! ```
+//!
+//! Sounds right on the listener are heard at half volume by Kira when spatialized; disable the
+//! spatialization of their [`SpatialEmitter`](crate::prelude::SpatialEmitter) to have them heard
+//! centered at full volume.
+//!
+//! The entity should have no parent, as its transform is not relative to it anymore, and its
+//! children do not follow the listener.
+use bevy::prelude::*;
+
+use super::listener::primary_listener;
+use crate::prelude::{AudioListener, PrimaryAudioListener};
+
+/// Component making the [`Transform`] of this entity relative to an audio listener.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Component)]
+pub struct ListenerRelative {
+    /// Listener entity to follow. When `None`, the [`PrimaryAudioListener`] is followed, or the
+    /// first listener found when none is marked as primary.
+    pub listener: Option<Entity>,
+}
+
+impl ListenerRelative {
+    /// Follows the given listener entity.
+    pub fn to(listener: Entity) -> Self {
+        Self {
+            listener: Some(listener),
+        }
+    }
+}
+
+pub(super) fn update_listener_relative(
+    q_listeners: Query<(&GlobalTransform, Has<PrimaryAudioListener>), With<AudioListener>>,
+    mut q_relative: Query<
+        (&ListenerRelative, &Transform, &mut GlobalTransform),
+        Without<AudioListener>,
+    >,
+) {
+    let primary = primary_listener(&q_listeners).copied();
+    for (relative, transform, mut global_transform) in &mut q_relative {
+        let listener = match relative.listener {
+            Some(entity) => q_listeners
+                .get(entity)
+                .ok()
+                .map(|(transform, _)| *transform),
+            None => primary,
+        };
+        // Sounds stay where they are when the listener is gone
+        let Some(listener) = listener else {
+            continue;
+        };
+        *global_transform = listener.mul_transform(*transform);
+    }
+}
@@ -0,0 +1,296 @@
+//! Environmental reverb, changing with the zone the listener is in.
+//!
+//! The sounds heard by spatial listeners are sent to a shared reverb. The amount sent and the
+//! parameters of the reverb follow the [`ReverbZone`]s around the listener: when the listener
+//! walks out of a zone, it crossfades into the zones it gets closer to over their blend distance,
+//! and overlapping zones are mixed together. Outside of all zones, no reverb is applied.
+use std::time::Duration;
+
+use bevy::prelude::*;
+use kira::effect::reverb::{ReverbBuilder, ReverbHandle};
+use kira::track::{TrackBuilder, TrackHandle, TrackId, TrackRoutes};
+use kira::tween::Tween;
+use kira::{ResourceLimitReached, Volume};
+
+use super::listener::primary_listener;
+use super::SpatialListenerHandle;
+use crate::prelude::{AudioListener, PrimaryAudioListener, SpatialWorld};
+use crate::AudioWorld;
+
+/// Shape of a [`ReverbZone`], centered on the [`GlobalTransform`] of its entity, and scaled and
+/// rotated by it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReverbZoneShape {
+    /// Sphere of the given radius.
+    Sphere {
+        /// Radius of the sphere.
+        radius: f32,
+    },
+    /// Box of the given size.
+    Box {
+        /// Half of the size of the box on each axis.
+        half_extents: Vec3,
+    },
+}
+
+impl ReverbZoneShape {
+    /// Distance from the point to the shape, 0 inside of it.
+    fn distance(&self, transform: &GlobalTransform, point: Vec3) -> f32 {
+        let (scale, rotation, center) = transform.to_scale_rotation_translation();
+        match *self {
+            Self::Sphere { radius } => {
+                (point.distance(center) - radius * scale.abs().max_element()).max(0.0)
+            }
+            Self::Box { half_extents } => {
+                let local = rotation.inverse() * (point - center);
+                (local.abs() - half_extents * scale.abs())
+                    .max(Vec3::ZERO)
+                    .length()
+            }
+        }
+    }
+}
+
+/// Parameters of the environmental reverb.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReverbParameters {
+    /// How much the room reverberates, between 0 and 1. Higher values sound like bigger rooms.
+    /// Defaults to 0.9.
+    pub feedback: f64,
+    /// How quickly high frequencies disappear from the reverberation, between 0 and 1. Defaults
+    /// to 0.1.
+    pub damping: f64,
+    /// Stereo width of the reverberation, from 0 (mono) to 1 (fully stereo). Defaults to 1.
+    pub stereo_width: f64,
+    /// Amplitude of the sound sent to the reverb. Defaults to 0.5.
+    pub send: f64,
+}
+
+impl Default for ReverbParameters {
+    fn default() -> Self {
+        Self {
+            feedback: 0.9,
+            damping: 0.1,
+            stereo_width: 1.0,
+            send: 0.5,
+        }
+    }
+}
+
+/// Zone of the world applying its reverb to the sounds heard by listeners within it.
+///
+/// The zone takes full effect inside its shape, and fades out linearly with the distance to the
+/// shape until the blend distance.
+#[derive(Debug, Copy, Clone, PartialEq, Component)]
+pub struct ReverbZone {
+    /// Shape of the zone.
+    pub shape: ReverbZoneShape,
+    /// Reverb applied within the zone.
+    pub reverb: ReverbParameters,
+    /// Distance around the shape over which the zone fades out.
+    pub blend_distance: f32,
+}
+
+impl Default for ReverbZone {
+    fn default() -> Self {
+        Self {
+            shape: ReverbZoneShape::Sphere { radius: 10.0 },
+            reverb: ReverbParameters::default(),
+            blend_distance: 5.0,
+        }
+    }
+}
+
+impl ReverbZone {
+    /// Weight of the zone at the given point, from 0 (outside of the zone and its blend distance)
+    /// to 1 (inside the zone).
+    fn weight(&self, transform: &GlobalTransform, point: Vec3) -> f32 {
+        let distance = self.shape.distance(transform, point);
+        if distance <= 0.0 {
+            1.0
+        } else if self.blend_distance > 0.0 {
+            (1.0 - distance / self.blend_distance).max(0.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Smallest change of the blended parameters sent to the reverb, avoiding to send commands to
+/// the audio engine on every frame for changes nobody can hear.
+const PARAMETER_THRESHOLD: f64 = 1e-3;
+
+/// Resource holding the shared environmental reverb the spatial listeners send their sounds to.
+///
+/// The tracks of the reverb are only created along with the first [`ReverbZone`], for games
+/// without zones not to process the reverb.
+#[derive(Resource)]
+pub struct EnvironmentReverb {
+    /// Duration of the transitions of the reverb as the listener moves between zones. Defaults to
+    /// 0.5 s.
+    pub transition: Duration,
+    tracks: Option<ReverbTracks>,
+    parameters: Option<ReverbParameters>,
+}
+
+impl Default for EnvironmentReverb {
+    fn default() -> Self {
+        Self {
+            transition: Duration::from_millis(500),
+            tracks: None,
+            parameters: None,
+        }
+    }
+}
+
+/// Mixer tracks of the environmental reverb.
+struct ReverbTracks {
+    /// Track the spatial listener outputs to, sending to the main track and the reverb track
+    send_track: TrackHandle,
+    reverb_track: TrackHandle,
+    reverb: ReverbHandle,
+}
+
+impl ReverbTracks {
+    fn new(audio_world: &mut AudioWorld) -> Result<Self, ResourceLimitReached> {
+        let mut builder = TrackBuilder::new();
+        let defaults = ReverbParameters::default();
+        let reverb = builder.add_effect(
+            ReverbBuilder::new()
+                .feedback(defaults.feedback)
+                .damping(defaults.damping)
+                .stereo_width(defaults.stereo_width)
+                .mix(1.0),
+        );
+        let reverb_track = audio_world.audio_manager.add_sub_track(builder)?;
+        let send_track =
+            audio_world.audio_manager.add_sub_track(
+                TrackBuilder::new().routes(
+                    TrackRoutes::parent(TrackId::Main)
+                        .with_route(&reverb_track, Volume::Amplitude(0.0)),
+                ),
+            )?;
+        Ok(Self {
+            send_track,
+            reverb_track,
+            reverb,
+        })
+    }
+}
+
+impl EnvironmentReverb {
+    /// Current parameters of the reverb, blended from the zones around the listener. `None`
+    /// when the listener is outside all zones.
+    pub fn parameters(&self) -> Option<ReverbParameters> {
+        self.parameters
+    }
+
+    /// Track the spatial listener outputs to, once the reverb has been created.
+    pub(crate) fn send_track(&self) -> Option<&TrackHandle> {
+        self.tracks.as_ref().map(|tracks| &tracks.send_track)
+    }
+
+    fn apply(&mut self, parameters: Option<ReverbParameters>) {
+        let changed = match (parameters, self.parameters) {
+            (Some(new), Some(old)) => [
+                new.feedback - old.feedback,
+                new.damping - old.damping,
+                new.stereo_width - old.stereo_width,
+                new.send - old.send,
+            ]
+            .iter()
+            .any(|difference| difference.abs() >= PARAMETER_THRESHOLD),
+            (new, old) => new.is_some() != old.is_some(),
+        };
+        let Some(tracks) = self.tracks.as_mut().filter(|_| changed) else {
+            return;
+        };
+        let tween = Tween {
+            duration: self.transition,
+            ..default()
+        };
+        let send = parameters.map_or(0.0, |parameters| parameters.send);
+        let result = tracks.send_track.set_route(
+            &tracks.reverb_track,
+            Volume::Amplitude(send.max(0.0)),
+            tween,
+        );
+        if let Err(err) = result {
+            error!("Cannot set the environment reverb send: {err}");
+        }
+        // Keep the reverb as it is while fading out, for the tail to sound as before
+        if let Some(parameters) = parameters {
+            tracks.reverb.set_feedback(parameters.feedback, tween);
+            tracks.reverb.set_damping(parameters.damping, tween);
+            tracks
+                .reverb
+                .set_stereo_width(parameters.stereo_width, tween);
+        }
+        self.parameters = parameters;
+    }
+}
+
+/// Blends the parameters of the zones around the listener.
+pub(super) fn update_reverb_zones(
+    mut commands: Commands,
+    mut reverb: ResMut<EnvironmentReverb>,
+    mut audio_world: ResMut<AudioWorld>,
+    spatial_world: Res<SpatialWorld>,
+    q_zones: Query<(&ReverbZone, &GlobalTransform)>,
+    q_listeners: Query<(&GlobalTransform, Has<PrimaryAudioListener>), With<AudioListener>>,
+    q_listener_handles: Query<Entity, With<SpatialListenerHandle>>,
+) {
+    if reverb.tracks.is_none() {
+        if q_zones.is_empty() {
+            return;
+        }
+        match ReverbTracks::new(&mut audio_world) {
+            Ok(tracks) => reverb.tracks = Some(tracks),
+            Err(err) => {
+                error_once!("Cannot create environment reverb tracks: {err}");
+                return;
+            }
+        }
+        // Kira listeners cannot change the track they output to, have the listener created again
+        // with the send track
+        for entity in &q_listener_handles {
+            commands.entity(entity).remove::<SpatialListenerHandle>();
+        }
+    }
+    let Some(listener) = primary_listener(&q_listeners) else {
+        reverb.apply(None);
+        return;
+    };
+    let listener = listener.translation();
+    let mut total_weight = 0.0;
+    let mut blended = ReverbParameters {
+        feedback: 0.0,
+        damping: 0.0,
+        stereo_width: 0.0,
+        send: 0.0,
+    };
+    for (zone, transform) in &q_zones {
+        // Zones are compared with the listener within their plane in 2D
+        let position = spatial_world.listener_point(listener, transform.translation().z);
+        let weight = zone.weight(transform, position) as f64;
+        if weight <= 0.0 {
+            continue;
+        }
+        total_weight += weight;
+        blended.feedback += zone.reverb.feedback * weight;
+        blended.damping += zone.reverb.damping * weight;
+        blended.stereo_width += zone.reverb.stereo_width * weight;
+        blended.send += zone.reverb.send * weight;
+    }
+    if total_weight <= 0.0 {
+        reverb.apply(None);
+        return;
+    }
+    // The reverb fades in with the weight of the zones, but the parameters only depend on their
+    // relative weights
+    blended.feedback /= total_weight;
+    blended.damping /= total_weight;
+    blended.stereo_width /= total_weight;
+    blended.send /= total_weight.max(1.0);
+    reverb.apply(Some(blended));
+}
@@ -0,0 +1,100 @@
+//! Shapes of spatial emitters, for sounds coming from more than a single point.
+//!
+//! A river, a road or the wind in a forest are heard from the part of them closest to the
+//! listener. Emitters with an [`EmitterShape`] other than a point are placed every frame at the
+//! closest point of their shape to the listener, which makes ambience follow the player as they
+//! walk along it; listeners inside volumes hear them at full volume, without panning.
+use bevy::prelude::*;
+
+/// Shape of a [`SpatialEmitter`](crate::prelude::SpatialEmitter), in the local space of its
+/// [`GlobalTransform`] (translated, rotated and scaled by it).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum EmitterShape {
+    /// Single point at the origin of the emitter. This is the default.
+    #[default]
+    Point,
+    /// Line segment between two points.
+    Segment {
+        /// Start of the segment.
+        start: Vec3,
+        /// End of the segment.
+        end: Vec3,
+    },
+    /// Connected line segments going through the points, in order.
+    Polyline(Vec<Vec3>),
+    /// Box of the given size, centered on the origin of the emitter.
+    Box {
+        /// Half of the size of the box on each axis.
+        half_extents: Vec3,
+    },
+    /// Sphere of the given radius, centered on the origin of the emitter.
+    Sphere {
+        /// Radius of the sphere.
+        radius: f32,
+    },
+}
+
+impl EmitterShape {
+    /// Point of the shape closest to the given point, in world space. Points inside of volumes
+    /// are their own closest point.
+    pub fn closest_point(&self, transform: &GlobalTransform, point: Vec3) -> Vec3 {
+        match self {
+            Self::Point => transform.translation(),
+            Self::Segment { start, end } => closest_point_on_segment(
+                transform.transform_point(*start),
+                transform.transform_point(*end),
+                point,
+            ),
+            Self::Polyline(points) => match points.as_slice() {
+                [] => transform.translation(),
+                [single] => transform.transform_point(*single),
+                points => points
+                    .windows(2)
+                    .map(|segment| {
+                        closest_point_on_segment(
+                            transform.transform_point(segment[0]),
+                            transform.transform_point(segment[1]),
+                            point,
+                        )
+                    })
+                    .min_by(|a, b| {
+                        a.distance_squared(point)
+                            .total_cmp(&b.distance_squared(point))
+                    })
+                    .unwrap(),
+            },
+            Self::Box { half_extents } => closest_point_in_volume(transform, point, |local| {
+                local.clamp(-*half_extents, *half_extents)
+            }),
+            // Non-uniform scales make an ellipsoid, for which this is an approximation
+            Self::Sphere { radius } => {
+                closest_point_in_volume(transform, point, |local| local.clamp_length_max(*radius))
+            }
+        }
+    }
+}
+
+/// Finds the closest point of a volume in its local space, where `closest` is computed.
+fn closest_point_in_volume(
+    transform: &GlobalTransform,
+    point: Vec3,
+    closest: impl Fn(Vec3) -> Vec3,
+) -> Vec3 {
+    let affine = transform.affine();
+    // Volumes scaled down to nothing are reduced to their origin
+    if affine.matrix3.determinant().abs() <= f32::EPSILON {
+        return transform.translation();
+    }
+    let local = affine.inverse().transform_point3(point);
+    affine.transform_point3(closest(local))
+}
+
+fn closest_point_on_segment(start: Vec3, end: Vec3, point: Vec3) -> Vec3 {
+    let segment = end - start;
+    let length_squared = segment.length_squared();
+    if length_squared <= f32::EPSILON {
+        return start;
+    }
+    let t = ((point - start).dot(segment) / length_squared).clamp(0.0, 1.0);
+    start + segment * t
+}
@@ -0,0 +1,192 @@
+//! Panning of spatial sounds across the speakers of a surround layout.
+//!
+//! When the audio backend outputs to a surround [`ChannelLayout`] (see
+//! [`AudioBackendSelector::Physical`](crate::prelude::AudioBackendSelector::Physical)), the sounds
+//! of spatial emitters are panned between the two speakers surrounding their direction relative
+//! to the listener, instead of between the left and right channels. Elevation is ignored, and the
+//! LFE channel is left silent.
+//!
+//! The front left and right speakers are fed through the mixer of Kira as usual. The other
+//! speakers are fed directly by the sounds, after their emitter attenuation and scaled by the
+//! volume of the main track set with [`AudioWorld::set_main_volume`], but do not go through the
+//! effects of the mixer tracks (including the environment reverb). Non-spatial sounds only play on
+//! the front speakers.
+//!
+//! Binaural rendering, meant for headphones, takes precedence over surround panning.
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use kira::Frame;
+
+use super::listener::primary_listener;
+use crate::backend::surround::{SurroundBus, FRONT_LEFT, FRONT_RIGHT, MAX_CHANNELS};
+use crate::prelude::{
+    AudioListener, ChannelLayout, PrimaryAudioListener, SpatialEmitter, SpatialWorld,
+};
+use crate::AudioWorld;
+
+/// Component added to spatial emitters panned across the speakers of a surround layout.
+#[derive(Debug, Component)]
+pub struct SurroundEmitter(pub(crate) Arc<SurroundState>);
+
+impl SurroundEmitter {
+    pub(crate) fn new(bus: Arc<SurroundBus>) -> Self {
+        let state = SurroundState {
+            gains: Default::default(),
+            attenuation: AtomicU32::new(1f32.to_bits()),
+            bus,
+        };
+        // Sounds start in front of the listener until the emitter is first updated
+        state.set_gains(&speaker_gains(state.bus.layout, 0.0));
+        Self(Arc::new(state))
+    }
+
+    /// Speaker layout the emitter is panned across.
+    pub fn layout(&self) -> ChannelLayout {
+        self.0.bus.layout
+    }
+
+    /// Gain of the sounds of the emitter on the given output channel, before distance
+    /// attenuation. Channels outside of the layout have a gain of 0.
+    pub fn gain(&self, channel: usize) -> f32 {
+        self.0
+            .gains
+            .get(channel)
+            .map_or(0.0, |gain| f32::from_bits(gain.load(Ordering::Relaxed)))
+    }
+}
+
+/// Surround panning shared between the ECS and the sounds of an emitter.
+#[derive(Debug)]
+pub(crate) struct SurroundState {
+    /// Gain of each channel, stored as the bits of `f32`s
+    gains: [AtomicU32; MAX_CHANNELS],
+    /// Distance attenuation of the emitter and volume of the main track, applied by Kira to the
+    /// front channels only, stored as the bits of an `f32`
+    attenuation: AtomicU32,
+    bus: Arc<SurroundBus>,
+}
+
+impl SurroundState {
+    fn set_gains(&self, gains: &[f32; MAX_CHANNELS]) {
+        for (gain, value) in self.gains.iter().zip(gains) {
+            gain.store(value.to_bits(), Ordering::Relaxed);
+        }
+    }
+}
+
+/// Constant power gains of each channel for a sound coming from the given azimuth, in degrees
+/// counter-clockwise from the front.
+fn speaker_gains(layout: ChannelLayout, azimuth: f32) -> [f32; MAX_CHANNELS] {
+    let mut gains = [0.0; MAX_CHANNELS];
+    let speakers = layout.speakers();
+    let azimuth = azimuth.rem_euclid(360.0);
+    for (i, speaker) in speakers.iter().enumerate() {
+        // Speakers are sorted by azimuth, the last pair wraps around the back
+        let next = &speakers[(i + 1) % speakers.len()];
+        let start = speaker.azimuth.rem_euclid(360.0);
+        let mut end = next.azimuth.rem_euclid(360.0);
+        if end <= start {
+            end += 360.0;
+        }
+        let mut position = azimuth;
+        if position < start {
+            position += 360.0;
+        }
+        if position <= end {
+            let amount = (position - start) / (end - start) * std::f32::consts::FRAC_PI_2;
+            gains[speaker.channel] = amount.cos();
+            gains[next.channel] = amount.sin();
+            break;
+        }
+    }
+    gains
+}
+
+/// Time for the panning and attenuation to follow the direction of the emitter, in seconds.
+const SMOOTHING_TIME: f64 = 0.02;
+
+/// Audio thread side of the surround panning, splitting the sound between the front channels
+/// rendered by Kira and the other channels of the surround bus.
+pub(crate) struct SurroundProcessor {
+    state: Arc<SurroundState>,
+    gains: [f32; MAX_CHANNELS],
+    attenuation: f32,
+}
+
+impl SurroundProcessor {
+    pub(crate) fn new(state: Arc<SurroundState>) -> Self {
+        let gains = std::array::from_fn(|channel| {
+            f32::from_bits(state.gains[channel].load(Ordering::Relaxed))
+        });
+        let attenuation = f32::from_bits(state.attenuation.load(Ordering::Relaxed));
+        Self {
+            state,
+            gains,
+            attenuation,
+        }
+    }
+
+    pub(crate) fn process(&mut self, input: Frame, dt: f64) -> Frame {
+        let amount = (1.0 - (-dt / SMOOTHING_TIME).exp()) as f32;
+        for (gain, target) in self.gains.iter_mut().zip(&self.state.gains) {
+            *gain += (f32::from_bits(target.load(Ordering::Relaxed)) - *gain) * amount;
+        }
+        let attenuation = f32::from_bits(self.state.attenuation.load(Ordering::Relaxed));
+        self.attenuation += (attenuation - self.attenuation) * amount;
+
+        let mono = (input.left + input.right) / 2.0;
+        for (channel, gain) in self.gains.iter().enumerate().skip(2) {
+            if *gain > 1e-5 {
+                self.state.bus.add(channel, mono * gain * self.attenuation);
+            }
+        }
+        Frame::new(
+            mono * self.gains[FRONT_LEFT],
+            mono * self.gains[FRONT_RIGHT],
+        )
+    }
+}
+
+pub(super) fn update_surround_emitters(
+    spatial_world: Res<SpatialWorld>,
+    audio_world: Res<AudioWorld>,
+    q_emitters: Query<(&SurroundEmitter, &SpatialEmitter, &GlobalTransform)>,
+    q_listeners: Query<(&GlobalTransform, Has<PrimaryAudioListener>), With<AudioListener>>,
+) {
+    let Some(listener) = primary_listener(&q_listeners) else {
+        // Spatial sounds are not heard without a listener
+        for (surround, _, _) in &q_emitters {
+            surround.0.set_gains(&[0.0; MAX_CHANNELS]);
+            surround
+                .0
+                .attenuation
+                .store(0f32.to_bits(), Ordering::Relaxed);
+        }
+        return;
+    };
+    let (_, rotation, listener) = listener.to_scale_rotation_translation();
+    // Spatial sounds reach the main track through the listener, whose track is left at full
+    // volume
+    let main_volume = audio_world.main_volume() as f32;
+
+    for (surround, emitter, transform) in &q_emitters {
+        let state = &surround.0;
+        let point = spatial_world.emitter_point(emitter, transform, listener);
+        let offset = spatial_world.audio_position(point) - spatial_world.audio_position(listener);
+        let distance = offset.length();
+        let direction = spatial_world.audio_orientation(rotation).inverse() * offset;
+        // Sounds right on (or above) the listener are heard from the front
+        let azimuth = if direction.x.abs() + direction.z.abs() > f32::EPSILON {
+            (-direction.x).atan2(-direction.z).to_degrees()
+        } else {
+            0.0
+        };
+        state.set_gains(&speaker_gains(state.bus.layout, azimuth));
+        state.attenuation.store(
+            (emitter.attenuation(distance) * main_volume).to_bits(),
+            Ordering::Relaxed,
+        );
+    }
+}
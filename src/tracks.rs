@@ -0,0 +1,116 @@
+//! Support for mixer tracks as entities.
+//!
+//! Adding an [`AudioTrack`] component to an entity creates a Kira sub-track for it. Audio sources
+//! can then be sent to it by setting their [`OutputDestination`] to
+//! [`OutputDestination::Track`] with the track entity. Tracks themselves are routed to the main
+//! track by default, but can be routed to other tracks by adding an [`OutputDestination`] to
+//! them as well.
+use bevy::prelude::*;
+use kira::track::{TrackBuilder, TrackHandle, TrackRoutes};
+
+use crate::effects::PendingEffects;
+use crate::sources::OutputDestination;
+use crate::{AudioEffectsSetup, AudioPlaybackSet, AudioSourceSetup, AudioWorld};
+
+#[doc(hidden)]
+#[allow(missing_docs)]
+pub mod prelude {
+    pub use super::{AudioTrack, AudioTrackBundle, AudioTrackHandle};
+}
+
+/// Track plugin. This is an internal plugin, useful for some separation of concerns.
+///
+/// It is automatically added by the main [`AudioPlugin`](crate::AudioPlugin).
+pub(crate) struct AudioTrackPlugin;
+
+impl Plugin for AudioTrackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            add_tracks
+                .in_set(AudioPlaybackSet::Update)
+                .after(AudioEffectsSetup)
+                .before(AudioSourceSetup),
+        );
+    }
+}
+
+/// Component creating a mixer track for this entity.
+///
+/// Note that these settings are only used in the setup of the track, and not kept in sync
+/// afterwards. Use the [`AudioTrackHandle`] component to control the track once created.
+#[derive(Debug, Component)]
+pub struct AudioTrack {
+    /// Initial volume of the track.
+    pub volume: f64,
+}
+
+impl Default for AudioTrack {
+    fn default() -> Self {
+        Self { volume: 1.0 }
+    }
+}
+
+/// [`Bundle`] for easy creation of mixer tracks.
+#[derive(Debug, Default, Bundle)]
+pub struct AudioTrackBundle {
+    /// Settings of the track.
+    pub track: AudioTrack,
+    /// Destination of the audio of this track.
+    pub output: OutputDestination,
+}
+
+/// Component holding the handle to the Kira track created for the [`AudioTrack`] of this entity.
+/// Access this component from your systems to control the track. Despawning the entity (or
+/// removing this component) removes the track from the audio engine.
+#[derive(Debug, Deref, DerefMut, Component)]
+pub struct AudioTrackHandle(pub TrackHandle);
+
+#[allow(clippy::type_complexity)]
+fn add_tracks(
+    mut commands: Commands,
+    mut audio_world: ResMut<AudioWorld>,
+    mut q_added: Query<
+        (
+            Entity,
+            &AudioTrack,
+            Option<&OutputDestination>,
+            Option<&mut PendingEffects>,
+        ),
+        Without<AudioTrackHandle>,
+    >,
+    q_tracks: Query<&AudioTrackHandle>,
+) {
+    for (entity, track, output_destination, effects) in &mut q_added {
+        let Some(parent) = output_destination
+            .copied()
+            .unwrap_or_default()
+            .resolve(&mut audio_world.audio_manager, &q_tracks)
+        else {
+            debug!("Parent track not ready");
+            continue;
+        };
+        let manager = &audio_world.audio_manager;
+        if manager.num_sub_tracks() >= manager.sub_track_capacity() {
+            warn_once!("Track limit reached, tracks will be created once others are removed");
+            continue;
+        }
+        let mut builder = TrackBuilder::new()
+            .volume(track.volume)
+            .routes(TrackRoutes::parent(parent));
+        if let Some(mut effects) = effects {
+            for effect in effects.0.drain(..) {
+                builder.add_built_effect(effect);
+            }
+        }
+        match audio_world.audio_manager.add_sub_track(builder) {
+            Ok(handle) => {
+                debug!("Added track in {entity:?}");
+                commands.entity(entity).insert(AudioTrackHandle(handle));
+            }
+            Err(err) => {
+                error!("Cannot create track for entity {entity:?}: {err}");
+            }
+        }
+    }
+}
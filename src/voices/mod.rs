@@ -0,0 +1,417 @@
+//! Polyphony limits, stealing voices from playing sounds when too many of them play at once.
+//!
+//! Each sound created by an audio source is a *voice*. Limits on the number of voices can be set
+//! per asset with the [`VoiceLimits`] resource, and per track or container entity with the
+//! [`VoiceLimit`] component. Limits are checked right before the sound of a new source is
+//! created; when one is reached, the [`VoiceStealing`] policy of the limit decides which voice
+//! is stopped to make room for the new one, if any.
+//!
+//! Sources which are stopped by a limit (or never started because of it) are marked with the
+//! [`VoiceStolen`] component.
+//!
+//! Voices of spatial sources can also be [virtualized](virtualization) while they cannot be heard.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use bevy::asset::UntypedAssetId;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::prelude::{
+    AudioListener, OutputDestination, PrimaryAudioListener, SpatialEmitter, SpatialWorld,
+};
+use crate::spatial::listener::primary_listener;
+use crate::{AudioPlaybackSet, AudioSourceSetup};
+
+use self::virtualization::{VirtualizationSettings, VoiceVirtualization};
+
+pub mod virtualization;
+
+#[doc(hidden)]
+#[allow(missing_docs)]
+pub mod prelude {
+    pub use super::virtualization::{VirtualizationSettings, Virtualized, VoicePriority};
+    pub use super::{VoiceLimit, VoiceLimits, VoiceStealing, VoiceStolen};
+}
+
+/// Voice plugin. This is an internal plugin, useful for some separation of concerns.
+///
+/// It is automatically added by the main [`AudioPlugin`](crate::AudioPlugin).
+pub(crate) struct VoicePlugin;
+
+impl Plugin for VoicePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VoiceLimits>()
+            .init_resource::<VoiceRegistry>()
+            .init_resource::<VirtualizationSettings>()
+            .add_systems(
+                PostUpdate,
+                virtualization::update_virtual_voices
+                    .in_set(AudioPlaybackSet::Update)
+                    .in_set(VoiceVirtualization)
+                    .before(AudioSourceSetup),
+            );
+    }
+}
+
+/// Policy deciding which voice stops when a [`VoiceLimit`] is reached.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum VoiceStealing {
+    /// Stop the voice which has been playing for the longest time. This is the default.
+    #[default]
+    Oldest,
+    /// Stop the voice with the lowest output level. The level is measured at the output of the
+    /// sound, before the volume of its track and the spatial attenuation are applied.
+    Quietest,
+    /// Stop the voice the farthest away from the [`AudioListener`] sounds are heard by. Voices
+    /// without a [`GlobalTransform`] are considered to be on the listener.
+    Farthest,
+    /// Keep the playing voices, and don't start the new one instead.
+    RejectNew,
+}
+
+/// Maximum number of voices playing at once, and what to do when a new one would go over it.
+///
+/// Add this component to an entity to limit the voices of the sources sent to it (when it is an
+/// [`AudioTrack`](crate::prelude::AudioTrack)), and of the sources among its descendants (using
+/// it as a container of sounds). Use the [`VoiceLimits`] resource to limit the voices of an
+/// asset instead.
+///
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_kira_components::prelude::*;
+/// fn setup(mut commands: Commands) {
+///     // At most 8 gunshots at once, replacing the oldest ones
+///     commands.spawn((
+///         AudioTrackBundle::default(),
+///         VoiceLimit::new(8, VoiceStealing::Oldest),
+///     ));
+/// }
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+pub struct VoiceLimit {
+    /// Maximum number of voices. A limit of 0 rejects all new voices.
+    pub max_voices: usize,
+    /// Policy used when the limit is reached.
+    pub stealing: VoiceStealing,
+}
+
+impl VoiceLimit {
+    /// Creates a voice limit with the given maximum number of voices and stealing policy.
+    pub fn new(max_voices: usize, stealing: VoiceStealing) -> Self {
+        Self {
+            max_voices,
+            stealing,
+        }
+    }
+}
+
+/// Resource holding the voice limits of assets. Each limit applies to all the sources playing
+/// the same asset, regardless of the type of the asset.
+#[derive(Debug, Default, Resource)]
+pub struct VoiceLimits(HashMap<UntypedAssetId, VoiceLimit>);
+
+impl VoiceLimits {
+    /// Sets the voice limit of an asset, replacing the previous one.
+    pub fn insert(&mut self, asset: impl Into<UntypedAssetId>, limit: VoiceLimit) {
+        self.0.insert(asset.into(), limit);
+    }
+
+    /// Removes the voice limit of an asset, returning it if there was one.
+    pub fn remove(&mut self, asset: impl Into<UntypedAssetId>) -> Option<VoiceLimit> {
+        self.0.remove(&asset.into())
+    }
+
+    /// Returns the voice limit of an asset.
+    pub fn get(&self, asset: impl Into<UntypedAssetId>) -> Option<&VoiceLimit> {
+        self.0.get(&asset.into())
+    }
+}
+
+/// Marker component added to audio sources whose voice has been stolen by a [`VoiceLimit`], or
+/// which were not started because of the [`VoiceStealing::RejectNew`] policy.
+///
+/// Stolen sources are quickly faded out, and their handle does not control any sound anymore.
+/// Audio files apply their [`AudioFileEndBehavior`](crate::prelude::AudioFileEndBehavior) as if
+/// they had finished playing. Sources are not created again while marked; remove this component
+/// to try playing them again.
+#[derive(Debug, Copy, Clone, Component)]
+#[component(storage = "SparseSet")]
+pub struct VoiceStolen;
+
+/// Duration of the fade out of stolen voices, in seconds, avoiding clicks.
+const STEAL_FADE_DURATION: f64 = 0.01;
+/// Time for the measured output level of a voice to decrease by a factor of `e`, in seconds.
+const LEVEL_RELEASE_TIME: f64 = 0.3;
+
+/// State of a voice shared between the sound on the audio thread and the registry.
+#[derive(Debug, Default)]
+pub(crate) struct VoiceState {
+    stolen: AtomicBool,
+    finished: AtomicBool,
+    /// Output level of the sound, stored as the bits of an `f32`
+    level: AtomicU32,
+}
+
+impl VoiceState {
+    fn steal(&self) {
+        self.stolen.store(true, Ordering::Relaxed);
+    }
+
+    fn level(&self) -> f32 {
+        f32::from_bits(self.level.load(Ordering::Relaxed))
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+}
+
+/// Audio thread side of a voice, applying the fade out once stolen and measuring the level of
+/// the sound.
+pub(crate) struct VoiceProcessor {
+    state: Arc<VoiceState>,
+    gain: f64,
+    level: f64,
+}
+
+impl VoiceProcessor {
+    pub(crate) fn new(state: Arc<VoiceState>) -> Self {
+        Self {
+            state,
+            gain: 1.0,
+            level: 0.0,
+        }
+    }
+
+    /// Processes a frame of the sound output.
+    pub(crate) fn process(&mut self, frame: kira::Frame, dt: f64) -> kira::Frame {
+        if self.state.stolen.load(Ordering::Relaxed) {
+            self.gain = (self.gain - dt / STEAL_FADE_DURATION).max(0.0);
+        }
+        let frame = frame * self.gain as f32;
+        let peak = frame.left.abs().max(frame.right.abs()) as f64;
+        self.level = peak.max(self.level * (-dt / LEVEL_RELEASE_TIME).exp());
+        self.state
+            .level
+            .store((self.level as f32).to_bits(), Ordering::Relaxed);
+        frame
+    }
+
+    /// Returns `true` once the voice has been stolen and faded out.
+    pub(crate) fn faded_out(&self) -> bool {
+        self.gain <= 0.0
+    }
+
+    /// Marks the voice as finished, freeing its place in the voice limits.
+    pub(crate) fn finish(&self) {
+        self.state.finished.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for VoiceProcessor {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Voice created by an audio source.
+struct Voice {
+    entity: Entity,
+    asset: UntypedAssetId,
+    track: Option<Entity>,
+    state: Arc<VoiceState>,
+}
+
+/// Internal resource keeping track of the playing voices, regardless of their source type.
+/// Voices are kept in creation order.
+#[derive(Default, Resource)]
+pub(crate) struct VoiceRegistry {
+    voices: Vec<Voice>,
+}
+
+impl VoiceRegistry {
+    /// Stops the voices of the entity, and removes them from the registry.
+    pub(crate) fn release(&mut self, entity: Entity) {
+        self.voices.retain(|voice| {
+            if voice.entity == entity {
+                voice.state.steal();
+            }
+            voice.entity != entity
+        });
+    }
+}
+
+/// Group of voices limited by a [`VoiceLimit`].
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum VoiceGroup {
+    Asset(UntypedAssetId),
+    Entity(Entity),
+}
+
+/// System parameter applying voice limits to new audio sources.
+#[derive(SystemParam)]
+pub(crate) struct VoiceAllocator<'w, 's> {
+    commands: Commands<'w, 's>,
+    registry: ResMut<'w, VoiceRegistry>,
+    asset_limits: Res<'w, VoiceLimits>,
+    q_limits: Query<'w, 's, &'static VoiceLimit>,
+    q_parents: Query<'w, 's, &'static Parent>,
+    q_transforms: Query<'w, 's, (&'static GlobalTransform, Option<&'static SpatialEmitter>)>,
+    q_listeners:
+        Query<'w, 's, (&'static GlobalTransform, Has<PrimaryAudioListener>), With<AudioListener>>,
+    spatial_world: Res<'w, SpatialWorld>,
+}
+
+impl<'w, 's> VoiceAllocator<'w, 's> {
+    /// Makes room for a new voice of the given source, stealing voices when limits are reached.
+    ///
+    /// Voices are stolen before the sound of the source is created, for the sound capacity they
+    /// hold to be freed for it. Returns the state of the new voice, to be given to its sound and
+    /// registered with [`Self::register`] once played, or `None` if the voice is rejected.
+    /// Rejected sources are marked with [`VoiceStolen`].
+    pub(crate) fn allocate(
+        &mut self,
+        entity: Entity,
+        asset: UntypedAssetId,
+        output: &OutputDestination,
+    ) -> Option<Arc<VoiceState>> {
+        self.registry
+            .voices
+            .retain(|voice| !voice.state.is_finished());
+
+        let mut limits = Vec::new();
+        if let Some(limit) = self.asset_limits.get(asset) {
+            limits.push((VoiceGroup::Asset(asset), *limit));
+        }
+        let track = match output {
+            OutputDestination::Track(track) => Some(*track),
+            OutputDestination::MainOutput => None,
+        };
+        for container in track
+            .into_iter()
+            .chain(self.q_parents.iter_ancestors(entity))
+        {
+            let group = VoiceGroup::Entity(container);
+            if let Ok(limit) = self.q_limits.get(container) {
+                if !limits.iter().any(|(other, _)| *other == group) {
+                    limits.push((group, *limit));
+                }
+            }
+        }
+        if limits.is_empty() {
+            return Some(Arc::default());
+        }
+
+        // Check all the limits before stealing, so that no voice is stolen for a rejected one
+        let rejected = limits.iter().any(|(group, limit)| {
+            limit.max_voices == 0
+                || (limit.stealing == VoiceStealing::RejectNew
+                    && self.group_voices(*group).len() >= limit.max_voices)
+        });
+        if rejected {
+            debug!("Voice limit reached, rejecting voice of {entity:?}");
+            self.commands.entity(entity).insert(VoiceStolen);
+            return None;
+        }
+
+        for (group, limit) in limits {
+            let mut voices = self.group_voices(group);
+            if voices.len() < limit.max_voices {
+                continue;
+            }
+            match limit.stealing {
+                VoiceStealing::Oldest => {}
+                VoiceStealing::Quietest => voices.sort_by(|&a, &b| {
+                    let level = |i: usize| self.registry.voices[i].state.level();
+                    level(a).total_cmp(&level(b))
+                }),
+                VoiceStealing::Farthest => {
+                    let distances = voices
+                        .iter()
+                        .map(|&i| self.listener_distance(self.registry.voices[i].entity))
+                        .collect::<Vec<_>>();
+                    let mut order = (0..voices.len()).collect::<Vec<_>>();
+                    order.sort_by(|&a, &b| distances[b].total_cmp(&distances[a]));
+                    voices = order.into_iter().map(|i| voices[i]).collect();
+                }
+                VoiceStealing::RejectNew => unreachable!("Rejected voices are handled above"),
+            }
+            let excess = voices.len() + 1 - limit.max_voices;
+            let mut stolen = voices.into_iter().take(excess).collect::<Vec<_>>();
+            // Remove from the back to keep the indices valid
+            stolen.sort_unstable_by(|a, b| b.cmp(a));
+            for i in stolen {
+                let voice = self.registry.voices.remove(i);
+                debug!("Voice limit reached, stealing voice of {:?}", voice.entity);
+                voice.state.steal();
+                if let Some(mut entity) = self.commands.get_entity(voice.entity) {
+                    entity.insert(VoiceStolen);
+                }
+            }
+        }
+        Some(Arc::default())
+    }
+
+    /// Registers the voice of a source once it has been given to a sound. Voices which are never
+    /// played would otherwise hold their place in the limits forever, as they are only freed once
+    /// their sound finishes.
+    pub(crate) fn register(
+        &mut self,
+        entity: Entity,
+        asset: UntypedAssetId,
+        output: &OutputDestination,
+        state: Arc<VoiceState>,
+    ) {
+        let track = match output {
+            OutputDestination::Track(track) => Some(*track),
+            OutputDestination::MainOutput => None,
+        };
+        self.registry.voices.push(Voice {
+            entity,
+            asset,
+            track,
+            state,
+        });
+    }
+
+    /// Indices in the registry of the voices in the group, from the oldest to the newest.
+    fn group_voices(&self, group: VoiceGroup) -> Vec<usize> {
+        self.registry
+            .voices
+            .iter()
+            .enumerate()
+            .filter(|(_, voice)| match group {
+                VoiceGroup::Asset(asset) => voice.asset == asset,
+                VoiceGroup::Entity(container) => {
+                    voice.track == Some(container)
+                        || self
+                            .q_parents
+                            .iter_ancestors(voice.entity)
+                            .any(|ancestor| ancestor == container)
+                }
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Distance of the entity to the listener sounds are heard by.
+    fn listener_distance(&self, entity: Entity) -> f32 {
+        let Ok((transform, emitter)) = self.q_transforms.get(entity) else {
+            return 0.0;
+        };
+        let Some(listener) = primary_listener(&self.q_listeners) else {
+            return 0.0;
+        };
+        let listener = listener.translation();
+        match emitter {
+            Some(emitter) => self
+                .spatial_world
+                .emitter_distance(emitter, transform, listener),
+            None => self
+                .spatial_world
+                .distance(listener, transform.translation()),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Virtualization of spatial voices too far away or too quiet to be heard.
+//!
+//! Virtual voices don't hold a sound in the audio engine, which frees the engine from processing
+//! them. Their sound is created again once they become audible; audio files keep track of their
+//! playback position while virtual, and resume from where they would be, but other sources start
+//! over. Virtualization is therefore disabled by default, enable it with
+//! [`VirtualizationSettings::enabled`].
+use bevy::prelude::*;
+
+use crate::prelude::{AudioListener, PrimaryAudioListener, SpatialEmitter, SpatialWorld};
+use crate::spatial::listener::primary_listener;
+use crate::InternalAudioMarker;
+
+/// Settings of the virtualization of spatial voices.
+#[derive(Debug, Clone, Resource)]
+pub struct VirtualizationSettings {
+    /// Virtualize the voices of sources with a [`SpatialEmitter`]. Disabled by default, as only
+    /// audio files resume from where they would be once their voice is real again.
+    pub enabled: bool,
+    /// Amplitude under which voices are virtualized, after the attenuation of their emitter
+    /// (the volume of the sources themselves is not taken into account). Voices beyond the
+    /// maximum distance of their emitter are always below it. Defaults to 0.001 (-60 dB).
+    pub audibility_threshold: f32,
+    /// Maximum number of spatial voices playing at once. The audible voices with the lowest
+    /// [`VoicePriority`], and then the lowest amplitude, are virtualized past this number.
+    pub max_real_voices: Option<usize>,
+}
+
+impl Default for VirtualizationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            audibility_threshold: 0.001,
+            max_real_voices: None,
+        }
+    }
+}
+
+/// Priority of the voice of a source, used to decide which voices stay real when
+/// [`VirtualizationSettings::max_real_voices`] is reached. Higher priorities are kept first. The
+/// default priority is 0.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Component)]
+pub struct VoicePriority(pub i32);
+
+/// Marker component added to sources whose voice is virtual.
+///
+/// Virtual sources have no [`AudioHandle`](crate::prelude::AudioHandle) component: it is removed
+/// when the voice becomes virtual, and inserted again once it becomes real.
+#[derive(Debug, Copy, Clone, Component)]
+#[component(storage = "SparseSet")]
+pub struct Virtualized;
+
+/// System set of the systems deciding which voices are virtual.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, SystemSet)]
+pub(crate) struct VoiceVirtualization;
+
+/// Factor applied to the audibility threshold of real voices, so that voices at the limit don't
+/// get virtualized and created again on every frame.
+const HYSTERESIS: f32 = 0.5;
+
+#[allow(clippy::type_complexity)]
+pub(super) fn update_virtual_voices(
+    mut commands: Commands,
+    settings: Res<VirtualizationSettings>,
+    q_sources: Query<
+        (
+            Entity,
+            &SpatialEmitter,
+            &GlobalTransform,
+            Option<&VoicePriority>,
+            Has<Virtualized>,
+        ),
+        With<InternalAudioMarker>,
+    >,
+    q_listeners: Query<(&GlobalTransform, Has<PrimaryAudioListener>), With<AudioListener>>,
+    spatial_world: Res<SpatialWorld>,
+) {
+    let listener = primary_listener(&q_listeners).map(GlobalTransform::translation);
+    let mut audible = Vec::new();
+    let mut set_virtual = |entity: Entity, virtualized: bool, is_virtual: bool| {
+        if virtualized && !is_virtual {
+            debug!("Virtualizing voice of {entity:?}");
+            commands.entity(entity).insert(Virtualized);
+        } else if !virtualized && is_virtual {
+            debug!("Making voice of {entity:?} real");
+            commands.entity(entity).remove::<Virtualized>();
+        }
+    };
+
+    for (entity, emitter, transform, priority, is_virtual) in &q_sources {
+        // Without listeners, there is no way to tell what is audible
+        let Some(distance) = listener
+            .map(|listener| spatial_world.emitter_distance(emitter, transform, listener))
+            .filter(|_| settings.enabled)
+        else {
+            set_virtual(entity, false, is_virtual);
+            continue;
+        };
+        let amplitude = emitter.attenuation(distance);
+        let threshold = if is_virtual {
+            settings.audibility_threshold
+        } else {
+            settings.audibility_threshold * HYSTERESIS
+        };
+        if amplitude > 0.0 && amplitude >= threshold {
+            audible.push((
+                entity,
+                priority.copied().unwrap_or_default(),
+                amplitude,
+                is_virtual,
+            ));
+        } else {
+            set_virtual(entity, true, is_virtual);
+        }
+    }
+
+    audible.sort_by(|a, b| b.1.cmp(&a.1).then(b.2.total_cmp(&a.2)));
+    let max_real_voices = settings.max_real_voices.unwrap_or(usize::MAX);
+    for (i, (entity, _, _, is_virtual)) in audible.into_iter().enumerate() {
+        set_virtual(entity, i >= max_real_voices, is_virtual);
+    }
+}